@@ -1,22 +1,208 @@
-use cosmic_config::{CosmicConfigEntry, cosmic_config_derive::CosmicConfigEntry};
+use cosmic_config::{
+    Config, ConfigGet, ConfigSet, CosmicConfigEntry, cosmic_config_derive::CosmicConfigEntry,
+};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+#[derive(Debug, Default, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+pub enum FadeEasing {
+    Linear,
+    EaseIn,
+    EaseOut,
+    #[default]
+    EaseInOut,
+}
+
+/// Action taken once the suspend idle time passes
+#[derive(Debug, Default, Deserialize, Serialize, Clone, PartialEq, Eq)]
+pub enum SuspendAction {
+    #[default]
+    Suspend,
+    Hibernate,
+    HybridSleep,
+    SuspendThenHibernate,
+    PowerOff,
+    /// Run a shell command
+    Command(String),
+    None,
+}
+
+/// Action taken once the battery is critically low
+#[derive(Debug, Default, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+pub enum CriticalBatteryAction {
+    /// Show a notification
+    #[default]
+    Warn,
+    Hibernate,
+    PowerOff,
+    None,
+}
+
+/// How inhibit requests from an application are honored
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+pub enum InhibitorPolicy {
+    /// Ignore inhibit requests
+    Ignore,
+    /// Honor idle inhibit requests, which also block suspending on idle, but
+    /// not suspend-only requests
+    IdleOnly,
+    /// Inhibit suspend, but never dimming, screen off, or locking
+    SuspendOnly,
+}
+
+/// Idle stages inhibited while a window is fullscreen
+#[derive(Debug, Default, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+pub struct FullscreenInhibit {
+    pub dim: bool,
+    pub screen_off: bool,
+    pub lock: bool,
+    pub suspend: bool,
+}
 
 #[derive(Debug, Deserialize, Serialize, Clone, CosmicConfigEntry)]
 pub struct CosmicIdleConfig {
-    /// Screen off idle time, in ms
+    /// Dim idle time, in ms
+    pub dim_time: Option<u32>,
+    /// Opacity of the fade color when dimmed, from 0.0 to 1.0
+    pub dim_opacity: f32,
+    /// Duration of fades, in ms. If 0, the screen turns off without fading.
+    pub fade_time: u32,
+    /// Easing curve of fades
+    pub fade_easing: FadeEasing,
+    /// Color to fade to, as RGBA from 0.0 to 1.0
+    pub fade_color: [f32; 4],
+    /// Deprecated: screen off idle time, in ms. Setting it sets both
+    /// `screen_off_on_battery_time` and `screen_off_on_ac_time`.
     pub screen_off_time: Option<u32>,
+    /// Screen off idle time when on battery, in ms
+    pub screen_off_on_battery_time: Option<u32>,
+    /// Screen off idle time when on ac, in ms
+    pub screen_off_on_ac_time: Option<u32>,
+    /// Lock idle time, in ms
+    pub lock_time: Option<u32>,
+    /// Lock the screen after it turns off
+    pub lock_on_screen_off: bool,
+    /// Time after the screen turns off during which input cancels locking, in ms
+    pub lock_grace_time: u32,
     /// Suspend idle time when on battery, in ms
     pub suspend_on_battery_time: Option<u32>,
     /// Suspend idle time when on ac, in ms
     pub suspend_on_ac_time: Option<u32>,
+    /// Suspend action when on battery
+    pub suspend_on_battery_action: SuspendAction,
+    /// Suspend action when on ac
+    pub suspend_on_ac_action: SuspendAction,
+    /// Battery percentage at or below which the battery is critical
+    pub critical_battery_percentage: Option<f64>,
+    /// Remaining battery time at or below which the battery is critical, in ms
+    pub critical_battery_time: Option<u32>,
+    /// Action when the battery becomes critical, while on battery
+    pub critical_battery_action: CriticalBatteryAction,
+    /// Time after which an inhibitor that hasn't been renewed is dropped, in ms
+    pub inhibitor_max_time: Option<u32>,
+    /// Per application name overrides of `inhibitor_max_time`
+    pub inhibitor_max_time_overrides: BTreeMap<String, Option<u32>>,
+    /// Inhibitor policies, by application name or Flatpak app ID. Inhibitors
+    /// from other applications are fully honored.
+    pub inhibitor_policies: BTreeMap<String, InhibitorPolicy>,
+    /// Idle stages inhibited while a window is fullscreen on any output
+    pub fullscreen_inhibit: FullscreenInhibit,
+    /// If not empty, only fullscreen windows with these app IDs inhibit idle stages
+    pub fullscreen_inhibit_app_ids: Vec<String>,
+    /// Inhibit suspend while an MPRIS media player is playing
+    pub media_playback_inhibit: bool,
+    /// Pause MPRIS media players when the screen turns off or locks
+    pub pause_media: bool,
+    /// Resume media players paused by `pause_media` when the user is active again
+    pub resume_media: bool,
+    /// Name of a seat whose idle state is used for the session. If unset, or
+    /// no seat has the name, the session is idle once all seats are idle.
+    pub primary_seat: Option<String>,
 }
 
 impl Default for CosmicIdleConfig {
     fn default() -> Self {
         Self {
+            dim_time: None,
+            dim_opacity: 0.5,
+            fade_time: 5000,
+            fade_easing: FadeEasing::EaseInOut,
+            fade_color: [0., 0., 0., 1.],
             screen_off_time: Some(15 * 60 * 1000),
+            screen_off_on_battery_time: Some(15 * 60 * 1000),
+            screen_off_on_ac_time: Some(15 * 60 * 1000),
+            lock_time: None,
+            lock_on_screen_off: true,
+            lock_grace_time: 500,
             suspend_on_battery_time: Some(15 * 60 * 1000),
             suspend_on_ac_time: Some(30 * 60 * 1000),
+            suspend_on_battery_action: SuspendAction::Suspend,
+            suspend_on_ac_action: SuspendAction::Suspend,
+            critical_battery_percentage: Some(5.),
+            critical_battery_time: None,
+            critical_battery_action: CriticalBatteryAction::Warn,
+            inhibitor_max_time: None,
+            inhibitor_max_time_overrides: BTreeMap::new(),
+            inhibitor_policies: BTreeMap::new(),
+            fullscreen_inhibit: FullscreenInhibit::default(),
+            fullscreen_inhibit_app_ids: Vec::new(),
+            media_playback_inhibit: false,
+            pause_media: false,
+            resume_media: false,
+            primary_seat: None,
+        }
+    }
+}
+
+impl CosmicIdleConfig {
+    /// Maximum inhibitor time for an application, in ms
+    pub fn inhibitor_max_time_for(&self, application_name: &str) -> Option<u32> {
+        self.inhibitor_max_time_overrides
+            .get(application_name)
+            .copied()
+            .unwrap_or(self.inhibitor_max_time)
+    }
+
+    /// Apply deprecated keys among `changed_keys`, after `update_keys`, for
+    /// settings apps that still write them. The keys replacing them are
+    /// written too, so the change persists.
+    pub fn update_deprecated_keys<T: AsRef<str>>(
+        &mut self,
+        config: &Config,
+        changed_keys: &[T],
+    ) -> Result<(), cosmic_config::Error> {
+        if changed_keys.iter().any(|x| x.as_ref() == "screen_off_time") {
+            self.screen_off_on_battery_time = self.screen_off_time;
+            self.screen_off_on_ac_time = self.screen_off_time;
+            config.set("screen_off_on_battery_time", self.screen_off_time)?;
+            config.set("screen_off_on_ac_time", self.screen_off_time)?;
         }
+        Ok(())
+    }
+
+    /// Copy the `screen_off_time` key used by older versions to the per power
+    /// source keys, if those haven't been set yet.
+    pub fn migrate(config: &Config) -> Result<(), cosmic_config::Error> {
+        let screen_off_time = match config.get::<Option<u32>>("screen_off_time") {
+            Ok(screen_off_time) => screen_off_time,
+            Err(err) if is_not_found(&err) => return Ok(()),
+            Err(err) => return Err(err),
+        };
+        for key in ["screen_off_on_battery_time", "screen_off_on_ac_time"] {
+            match config.get::<Option<u32>>(key) {
+                Err(err) if is_not_found(&err) => config.set(key, screen_off_time)?,
+                // Set, or failed to parse; leave it alone
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+fn is_not_found(err: &cosmic_config::Error) -> bool {
+    match err {
+        cosmic_config::Error::NotFound => true,
+        cosmic_config::Error::GetKey(_, err) => err.kind() == std::io::ErrorKind::NotFound,
+        _ => false,
     }
 }
@@ -0,0 +1,144 @@
+// `com.system76.CosmicIdle` interface, for panel applets and scripts to
+// inspect and control cosmic-idle
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use crate::{Event, EventSender, inhibitor::Inhibitors};
+
+const NAME: &str = "com.system76.CosmicIdle";
+const PATH: &str = "/com/system76/CosmicIdle";
+
+// How far the session has progressed toward idle
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Stage {
+    #[default]
+    Active,
+    Dimmed,
+    // Fading out before the screen turns off
+    Fading,
+    ScreenOff,
+    // Session is locked, according to logind
+    Locked,
+}
+
+impl Stage {
+    // Whether the screen is off or locked
+    pub fn screensaver_active(self) -> bool {
+        matches!(self, Self::Fading | Self::ScreenOff | Self::Locked)
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Active => "active",
+            Self::Dimmed => "dimmed",
+            Self::Fading => "fading",
+            Self::ScreenOff => "screen-off",
+            Self::Locked => "locked",
+        }
+    }
+}
+
+// Idle times in ms, after inhibitors and battery state are applied. `None`
+// if the stage is disabled or inhibited.
+#[derive(Debug, Default)]
+pub struct Timeouts {
+    pub dim: Option<u32>,
+    pub screen_off: Option<u32>,
+    pub lock: Option<u32>,
+    pub suspend: Option<u32>,
+}
+
+// Control state, updated by `State`
+#[derive(Debug, Default)]
+pub struct Status {
+    pub stage: Stage,
+    pub timeouts: Timeouts,
+}
+
+struct Control {
+    inhibitors: Inhibitors,
+    event_sender: EventSender,
+    status: Arc<Mutex<Status>>,
+}
+
+#[zbus::interface(name = "com.system76.CosmicIdle")]
+impl Control {
+    fn get_stage(&self) -> &'static str {
+        self.status.lock().unwrap().stage.as_str()
+    }
+
+    // Application name, reason, and DBus sender of each inhibitor
+    fn list_inhibitors(&self) -> Vec<(String, String, String)> {
+        self.inhibitors.list()
+    }
+
+    // Enabled idle times, in ms, keyed by stage
+    fn get_timeouts(&self) -> HashMap<&'static str, u32> {
+        let status = self.status.lock().unwrap();
+        let timeouts = &status.timeouts;
+        [
+            ("dim", timeouts.dim),
+            ("screen-off", timeouts.screen_off),
+            ("lock", timeouts.lock),
+            ("suspend", timeouts.suspend),
+        ]
+        .into_iter()
+        .filter_map(|(stage, time)| Some((stage, time?)))
+        .collect()
+    }
+
+    // Reset the time of an application's inhibitors toward their maximum time
+    async fn renew_inhibitors(&self, application_name: String) {
+        self.inhibitors.renew(&application_name).await;
+    }
+
+    // Inhibit idle and suspend for `seconds`, or stop pausing if 0
+    fn pause_for(&self, seconds: u32) {
+        let duration = Duration::from_secs(seconds.into());
+        let _ = self.event_sender.send(Event::PauseFor(duration));
+    }
+
+    fn screen_off_now(&self) {
+        let _ = self.event_sender.send(Event::SetScreensaverActive(true));
+    }
+
+    fn lock_now(&self) {
+        let _ = self.event_sender.send(Event::Lock);
+    }
+
+    fn suspend_now(&self) {
+        let _ = self.event_sender.send(Event::Suspend);
+    }
+
+    #[zbus(signal)]
+    async fn stage_changed(
+        emitter: &zbus::object_server::SignalEmitter<'_>,
+        stage: &str,
+    ) -> zbus::Result<()>;
+}
+
+pub async fn emit_stage_changed(conn: &zbus::Connection, stage: Stage) -> zbus::Result<()> {
+    let emitter = zbus::object_server::SignalEmitter::new(conn, PATH)?;
+    Control::stage_changed(&emitter, stage.as_str()).await
+}
+
+pub async fn serve(
+    conn: zbus::Connection,
+    inhibitors: Inhibitors,
+    event_sender: EventSender,
+    status: Arc<Mutex<Status>>,
+) -> zbus::Result<()> {
+    let control = Control {
+        inhibitors,
+        event_sender,
+        status,
+    };
+    conn.object_server().at(PATH, control).await?;
+    conn.request_name_with_flags(NAME, zbus::fdo::RequestNameFlags::ReplaceExisting.into())
+        .await?;
+    Ok(())
+}
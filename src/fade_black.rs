@@ -1,6 +1,11 @@
 // Layer shell surface that fades to black, before setting DPMS off.
+// Also used to dim the screen, by fading to a partial opacity.
 
-use keyframe::{ease, functions::EaseInOut};
+use cosmic_idle_config::{CosmicIdleConfig, FadeEasing};
+use keyframe::{
+    ease,
+    functions::{EaseIn, EaseInOut, EaseOut, Linear},
+};
 use std::time::{Duration, Instant};
 use wayland_client::{
     Connection, Dispatch, QueueHandle, delegate_noop,
@@ -8,14 +13,12 @@ use wayland_client::{
 };
 use wayland_protocols::wp::{
     single_pixel_buffer::v1::client::wp_single_pixel_buffer_manager_v1,
-    viewporter::client::wp_viewport,
+    tablet::zv2::client::zwp_tablet_tool_v2, viewporter::client::wp_viewport,
 };
 use wayland_protocols_wlr::layer_shell::v1::client::{zwlr_layer_shell_v1, zwlr_layer_surface_v1};
 
 use crate::{State, StateInner};
 
-const FADE_TIME: Duration = Duration::from_secs(5);
-
 #[derive(Debug)]
 pub struct FadeBlackSurface {
     surface: wl_surface::WlSurface,
@@ -23,10 +26,19 @@ pub struct FadeBlackSurface {
     viewport: wp_viewport::WpViewport,
     has_first_configure: bool,
     started: Instant,
+    duration: Duration,
+    // Alpha at `started`, and alpha to fade to
+    from_alpha: f64,
+    to_alpha: f64,
 }
 
 impl FadeBlackSurface {
-    pub fn new(inner: &StateInner, output: &wl_output::WlOutput) -> Self {
+    pub fn new(
+        inner: &StateInner,
+        output: &wl_output::WlOutput,
+        alpha: f64,
+        duration: Duration,
+    ) -> Self {
         let surface = inner.compositor.create_surface(&inner.qh, ());
         let layer_surface = inner.layer_shell.get_layer_surface(
             &surface,
@@ -46,28 +58,89 @@ impl FadeBlackSurface {
             viewport,
             has_first_configure: false,
             started: Instant::now(),
+            duration,
+            from_alpha: 0.,
+            to_alpha: alpha,
         }
     }
 
     pub fn is_done(&self) -> bool {
-        self.started.elapsed() > FADE_TIME
+        self.started.elapsed() > self.duration
+    }
+
+    // Whether this fades fully to the fade color, rather than dimming
+    pub fn is_black(&self) -> bool {
+        self.to_alpha >= 1.
+    }
+
+    // Whether this fades back in, and should be destroyed when done
+    pub fn is_transparent(&self) -> bool {
+        self.to_alpha <= 0.
+    }
+
+    fn alpha(&self, conf: &CosmicIdleConfig) -> f64 {
+        let time = if self.duration.is_zero() {
+            1.
+        } else {
+            (self.started.elapsed().as_secs_f64() / self.duration.as_secs_f64()).min(1.)
+        };
+        let (from, to) = (self.from_alpha, self.to_alpha);
+        match conf.fade_easing {
+            FadeEasing::Linear => ease(Linear, from, to, time),
+            FadeEasing::EaseIn => ease(EaseIn, from, to, time),
+            FadeEasing::EaseOut => ease(EaseOut, from, to, time),
+            FadeEasing::EaseInOut => ease(EaseInOut, from, to, time),
+        }
+    }
+
+    // Start a new fade from the current alpha
+    pub fn fade_to(
+        &mut self,
+        inner: &StateInner,
+        conf: &CosmicIdleConfig,
+        alpha: f64,
+        duration: Duration,
+    ) {
+        let was_done = self.is_done();
+        self.from_alpha = self.alpha(conf);
+        self.to_alpha = alpha;
+        self.started = Instant::now();
+        self.duration = duration;
+        // Frame callbacks stop once a fade is done, so restart them
+        if was_done && self.has_first_configure {
+            self.update(inner, conf);
+        }
+    }
+
+    // Redraw if no longer animating, so config changes apply
+    pub fn redraw(&self, inner: &StateInner, conf: &CosmicIdleConfig) {
+        if self.is_done() && self.has_first_configure {
+            self.update(inner, conf);
+        }
     }
 
-    fn configure(&mut self, inner: &StateInner, width: u32, height: u32) {
+    fn configure(&mut self, inner: &StateInner, conf: &CosmicIdleConfig, width: u32, height: u32) {
         self.viewport.set_destination(width as i32, height as i32);
         if !self.has_first_configure {
-            self.update(inner);
+            self.update(inner, conf);
             self.has_first_configure = true;
         }
     }
 
-    pub fn update(&self, inner: &StateInner) {
-        let time = self.started.elapsed().as_secs_f64() / FADE_TIME.as_secs_f64();
-        let alpha = ease(EaseInOut, 0., u32::MAX as f64, time) as u32;
+    pub fn update(&self, inner: &StateInner, conf: &CosmicIdleConfig) {
+        // Buffer is premultiplied
+        let alpha = self.alpha(conf) * f64::from(conf.fade_color[3].clamp(0., 1.));
+        let [r, g, b, a] = [
+            conf.fade_color[0],
+            conf.fade_color[1],
+            conf.fade_color[2],
+            1.,
+        ]
+        .map(|c| (f64::from(c.clamp(0., 1.)) * alpha * u32::MAX as f64) as u32);
         let buffer =
             inner
                 .single_pixel_buffer_manager
-                .create_u32_rgba_buffer(0, 0, 0, alpha, &inner.qh, ());
+                .create_u32_rgba_buffer(r, g, b, a, &inner.qh, ());
         self.surface.attach(Some(&buffer), 0, 0);
         self.surface.frame(&inner.qh, self.surface.clone());
         self.surface.damage(0, 0, i32::MAX, i32::MAX);
@@ -104,7 +177,7 @@ impl Dispatch<zwlr_layer_surface_v1::ZwlrLayerSurfaceV1, ()> for State {
                         && &fade_surface.layer_surface == obj
                     {
                         fade_surface.layer_surface.ack_configure(serial);
-                        fade_surface.configure(&state.inner, width, height);
+                        fade_surface.configure(&state.inner, &state.conf, width, height);
                         break;
                     }
                 }
@@ -130,17 +203,21 @@ impl Dispatch<wl_callback::WlCallback, wl_surface::WlSurface> for State {
                         && &fade_surface.surface == surface
                     {
                         if fade_surface.is_done() {
-                            // All outputs are done fading
-                            if state
-                                .outputs
-                                .iter()
-                                .flat_map(|o| o.fade_surface.as_ref())
-                                .all(|s| s.is_done())
+                            if fade_surface.is_transparent() {
+                                // Done fading back in
+                                output.fade_surface = None;
+                            } else if fade_surface.is_black()
+                                && state
+                                    .outputs
+                                    .iter()
+                                    .flat_map(|o| o.fade_surface.as_ref())
+                                    .all(|s| s.is_black() && s.is_done())
                             {
+                                // All outputs are done fading to black
                                 state.fade_done();
                             }
                         } else {
-                            fade_surface.update(&state.inner);
+                            fade_surface.update(&state.inner, &state.conf);
                         }
                         break;
                     }
@@ -176,6 +253,28 @@ impl Dispatch<wl_pointer::WlPointer, ()> for State {
     }
 }
 
+impl Dispatch<zwp_tablet_tool_v2::ZwpTabletToolV2, ()> for State {
+    fn event(
+        _: &mut Self,
+        tool: &zwp_tablet_tool_v2::ZwpTabletToolV2,
+        event: zwp_tablet_tool_v2::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        match event {
+            zwp_tablet_tool_v2::Event::ProximityIn { serial, .. } => {
+                // Hide the tablet cursor too, like the pointer
+                tool.set_cursor(serial, None, 0, 0);
+            }
+            zwp_tablet_tool_v2::Event::Removed => {
+                tool.destroy();
+            }
+            _ => {}
+        }
+    }
+}
+
 delegate_noop!(State: ignore wl_buffer::WlBuffer);
 delegate_noop!(State: ignore wl_surface::WlSurface);
 delegate_noop!(State: wp_single_pixel_buffer_manager_v1::WpSinglePixelBufferManagerV1);
@@ -1,115 +1,173 @@
 // https://specifications.freedesktop.org/idle-inhibit-spec/latest
 // https://invent.kde.org/plasma/kscreenlocker/-/blob/master/dbus/org.freedesktop.ScreenSaver.xml
 
-use futures_lite::StreamExt;
-use std::sync::{
-    Arc, Mutex,
-    atomic::{AtomicU32, Ordering},
+use std::{
+    sync::{Arc, Mutex},
+    time::Instant,
 };
 
-use crate::{Event, EventSender};
+use crate::{
+    Event, EventSender,
+    inhibitor::{InhibitFlags, Inhibitors},
+};
+
+// Clients vary in which path they use
+const PATHS: [&str; 2] = ["/ScreenSaver", "/org/freedesktop/ScreenSaver"];
+const POWER_MANAGEMENT_PATH: &str = "/org/freedesktop/PowerManagement/Inhibit";
 
-#[derive(Debug)]
-pub struct Inhibitor {
-    cookie: u32,
-    application_name: String,
-    reason_for_inhibit: String,
-    client: zbus::names::UniqueName<'static>,
+// Screensaver state, updated by `State`
+#[derive(Debug, Default)]
+pub struct Status {
+    // When the screensaver became active, if it is
+    pub active_since: Option<Instant>,
+    // When the user was last active, if currently idle
+    pub idle_since: Option<Instant>,
+}
+
+fn secs_since(instant: Option<Instant>) -> u32 {
+    instant.map_or(0, |x| x.elapsed().as_secs().try_into().unwrap_or(u32::MAX))
 }
 
 #[derive(Clone)]
 struct Screensaver {
-    inhibitors: Arc<Mutex<Vec<Inhibitor>>>,
-    last_cookie: Arc<AtomicU32>,
+    inhibitors: Inhibitors,
     event_sender: EventSender,
+    status: Arc<Mutex<Status>>,
 }
 
 #[zbus::interface(name = "org.freedesktop.ScreenSaver")]
 impl Screensaver {
-    fn inhibit(
+    async fn inhibit(
         &mut self,
         application_name: String,
         reason_for_inhibit: String,
         #[zbus(header)] header: zbus::message::Header<'_>,
     ) -> u32 {
-        let cookie = self.last_cookie.fetch_add(1, Ordering::Relaxed) + 1;
-        if let Some(sender) = header.sender() {
-            log::info!(
-                "Added screensaver inhibitor for application '{}' {:?}, reason: {}, cookie: {}",
-                application_name,
-                sender,
-                reason_for_inhibit,
-                cookie
-            );
-            let mut inhibitors = self.inhibitors.lock().unwrap();
-            if inhibitors.is_empty() {
-                let _ = self.event_sender.send(Event::ScreensaverInhibit(true));
-            }
-            inhibitors.push(Inhibitor {
-                cookie,
+        // Inhibits idle, per the spec, which includes suspending on idle
+        self.inhibitors
+            .add(
                 application_name,
                 reason_for_inhibit,
-                client: sender.to_owned(),
-            });
-        }
-        cookie
+                &header,
+                InhibitFlags::IDLE,
+            )
+            .await
+    }
+
+    async fn un_inhibit(&mut self, cookie: u32) {
+        self.inhibitors.remove(cookie).await;
+    }
+
+    fn get_active(&self) -> bool {
+        self.status.lock().unwrap().active_since.is_some()
     }
 
-    fn un_inhibit(&mut self, cookie: u32) {
-        let mut inhibitors = self.inhibitors.lock().unwrap();
-        if let Some(idx) = inhibitors.iter().position(|x| x.cookie == cookie) {
-            let inhibitor = inhibitors.remove(idx);
-            if inhibitors.is_empty() {
-                let _ = self.event_sender.send(Event::ScreensaverInhibit(false));
-            }
-            log::info!(
-                "Removed screensaver inhibitor for application '{}' {:?}, reason: {}, cookie: {}",
-                inhibitor.application_name,
-                inhibitor.client,
-                inhibitor.reason_for_inhibit,
-                inhibitor.cookie
-            );
-        }
+    fn set_active(&self, e: bool) -> bool {
+        let _ = self.event_sender.send(Event::SetScreensaverActive(e));
+        true
     }
+
+    fn lock(&self) {
+        let _ = self.event_sender.send(Event::Lock);
+    }
+
+    // Seconds the screensaver has been active
+    fn get_active_time(&self) -> u32 {
+        secs_since(self.status.lock().unwrap().active_since)
+    }
+
+    // Seconds the session has been idle
+    fn get_session_idle_time(&self) -> u32 {
+        secs_since(self.status.lock().unwrap().idle_since)
+    }
+
+    fn simulate_user_activity(&self) {
+        let _ = self.event_sender.send(Event::SimulateUserActivity);
+    }
+
+    #[zbus(signal)]
+    async fn active_changed(
+        emitter: &zbus::object_server::SignalEmitter<'_>,
+        new_value: bool,
+    ) -> zbus::Result<()>;
 }
 
-pub async fn serve(event_sender: EventSender) -> zbus::Result<()> {
-    let inhibitors = Arc::new(Mutex::new(Vec::new()));
+// https://people.freedesktop.org/~hughsient/temp/power-management-spec-0.3.html
+struct PowerManagement {
+    inhibitors: Inhibitors,
+}
 
+#[zbus::interface(name = "org.freedesktop.PowerManagement.Inhibit")]
+impl PowerManagement {
+    async fn inhibit(
+        &mut self,
+        application: String,
+        reason: String,
+        #[zbus(header)] header: zbus::message::Header<'_>,
+    ) -> u32 {
+        // Only inhibits suspend, per the spec
+        self.inhibitors
+            .add(application, reason, &header, InhibitFlags::SUSPEND)
+            .await
+    }
+
+    async fn un_inhibit(&mut self, cookie: u32) {
+        self.inhibitors.remove(cookie).await;
+    }
+
+    fn has_inhibit(&self) -> bool {
+        self.inhibitors.has_inhibit()
+    }
+
+    #[zbus(signal)]
+    async fn has_inhibit_changed(
+        emitter: &zbus::object_server::SignalEmitter<'_>,
+        has_inhibit: bool,
+    ) -> zbus::Result<()>;
+}
+
+pub async fn emit_active_changed(conn: &zbus::Connection, active: bool) -> zbus::Result<()> {
+    for path in PATHS {
+        let emitter = zbus::object_server::SignalEmitter::new(conn, path)?;
+        Screensaver::active_changed(&emitter, active).await?;
+    }
+    Ok(())
+}
+
+pub async fn emit_has_inhibit_changed(
+    conn: &zbus::Connection,
+    has_inhibit: bool,
+) -> zbus::Result<()> {
+    let emitter = zbus::object_server::SignalEmitter::new(conn, POWER_MANAGEMENT_PATH)?;
+    PowerManagement::has_inhibit_changed(&emitter, has_inhibit).await
+}
+
+pub async fn serve(
+    conn: zbus::Connection,
+    inhibitors: Inhibitors,
+    event_sender: EventSender,
+    status: Arc<Mutex<Status>>,
+) -> zbus::Result<()> {
     let screensaver = Screensaver {
         inhibitors: inhibitors.clone(),
-        event_sender: event_sender.clone(),
-        last_cookie: Arc::new(AtomicU32::new(0)),
+        event_sender,
+        status,
     };
+    let power_management = PowerManagement { inhibitors };
 
-    // Clients vary in which path they use
-    let conn = zbus::connection::Builder::session()?
-        .serve_at("/ScreenSaver", screensaver.clone())?
-        .serve_at("/org/freedesktop/ScreenSaver", screensaver)?
-        .build()
+    for path in PATHS {
+        conn.object_server().at(path, screensaver.clone()).await?;
+    }
+    conn.object_server()
+        .at(POWER_MANAGEMENT_PATH, power_management)
         .await?;
-    conn.request_name_with_flags(
+    for name in [
         "org.freedesktop.ScreenSaver",
-        zbus::fdo::RequestNameFlags::ReplaceExisting.into(),
-    )
-    .await?;
-
-    // If a client disconnects from DBus, remove any inhibitors it has added.
-    let dbus = zbus::fdo::DBusProxy::new(&conn).await?;
-    let mut name_owner_stream = dbus.receive_name_owner_changed().await?;
-    while let Some(event) = name_owner_stream.next().await {
-        let args = event.args()?;
-        if args.new_owner.is_none()
-            && let zbus::names::BusName::Unique(name) = args.name
-        {
-            let mut inhibitors = inhibitors.lock().unwrap();
-            if !inhibitors.is_empty() {
-                inhibitors.retain(|inhibitor| inhibitor.client != name);
-                if inhibitors.is_empty() {
-                    let _ = event_sender.send(Event::ScreensaverInhibit(false));
-                }
-            }
-        }
+        "org.freedesktop.PowerManagement",
+        "org.freedesktop.PowerManagement.Inhibit",
+    ] {
+        conn.request_name_with_flags(name, zbus::fdo::RequestNameFlags::ReplaceExisting.into())
+            .await?;
     }
 
     Ok(())
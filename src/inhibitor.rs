@@ -0,0 +1,427 @@
+// Inhibitors shared by the DBus interfaces that can add them
+
+use cosmic_idle_config::{CosmicIdleConfig, InhibitorPolicy};
+use futures_lite::StreamExt;
+use std::{
+    collections::BTreeMap,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU32, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use crate::{Event, EventSender, freedesktop_screensaver};
+
+// What an inhibitor prevents
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct InhibitFlags {
+    // Dimming, screen off, locking, and suspending on idle
+    pub idle: bool,
+    // Suspending on idle, without the other idle stages
+    pub suspend: bool,
+}
+
+impl InhibitFlags {
+    pub const ALL: Self = Self {
+        idle: true,
+        suspend: true,
+    };
+    pub const IDLE: Self = Self {
+        idle: true,
+        suspend: false,
+    };
+    pub const SUSPEND: Self = Self {
+        idle: false,
+        suspend: true,
+    };
+}
+
+impl std::ops::BitOr for InhibitFlags {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self {
+            idle: self.idle || rhs.idle,
+            suspend: self.suspend || rhs.suspend,
+        }
+    }
+}
+
+// Where an inhibitor was added from
+#[derive(Debug, PartialEq)]
+enum Source {
+    Client(zbus::names::UniqueName<'static>),
+    // A logind inhibitor lock, such as from `systemd-inhibit`
+    Logind,
+    // An MPRIS media player that is playing, by bus name
+    Mpris(String),
+}
+
+impl std::fmt::Display for Source {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Client(name) => write!(f, "{}", name),
+            Self::Logind => write!(f, "logind"),
+            Self::Mpris(name) => write!(f, "{}", name),
+        }
+    }
+}
+
+// Which inhibitors are honored, from the config
+#[derive(Debug, Default, PartialEq)]
+pub struct Settings {
+    policies: BTreeMap<String, InhibitorPolicy>,
+    media_playback_inhibit: bool,
+}
+
+impl Settings {
+    pub fn new(conf: &CosmicIdleConfig) -> Self {
+        Self {
+            policies: conf.inhibitor_policies.clone(),
+            media_playback_inhibit: conf.media_playback_inhibit,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Inhibitor {
+    cookie: u32,
+    application_name: String,
+    reason_for_inhibit: String,
+    source: Source,
+    // Flatpak app ID of the client, if sandboxed
+    app_id: Option<String>,
+    flags: InhibitFlags,
+    // When added, or last renewed
+    renewed: Instant,
+}
+
+impl Inhibitor {
+    // What the inhibitor prevents, after applying settings and the application's policy
+    fn effective_flags(&self, settings: &Settings) -> InhibitFlags {
+        if matches!(self.source, Source::Mpris(_)) && !settings.media_playback_inhibit {
+            return InhibitFlags::default();
+        }
+        let policies = &settings.policies;
+        let policy = policies
+            .get(&self.application_name)
+            .or_else(|| self.app_id.as_ref().and_then(|app_id| policies.get(app_id)));
+        match policy {
+            None => self.flags,
+            Some(InhibitorPolicy::Ignore) => InhibitFlags::default(),
+            Some(InhibitorPolicy::IdleOnly) => InhibitFlags {
+                idle: self.flags.idle,
+                suspend: false,
+            },
+            Some(InhibitorPolicy::SuspendOnly) => InhibitFlags {
+                idle: false,
+                suspend: self.flags.suspend,
+            },
+        }
+    }
+
+    // When the inhibitor should be dropped, if it has a maximum time
+    fn expiry(&self, max_time: &impl Fn(&str) -> Option<Duration>) -> Option<Instant> {
+        // Removed by their source when no longer needed
+        if matches!(self.source, Source::Logind | Source::Mpris(_)) {
+            return None;
+        }
+        Some(self.renewed + max_time(&self.application_name)?)
+    }
+}
+
+fn flags(inhibitors: &[Inhibitor], settings: &Settings) -> InhibitFlags {
+    inhibitors.iter().fold(InhibitFlags::default(), |flags, x| {
+        flags | x.effective_flags(settings)
+    })
+}
+
+// Flatpak app ID of a DBus client, from the `.flatpak-info` file in its sandbox
+async fn flatpak_app_id(
+    conn: &zbus::Connection,
+    name: &zbus::names::UniqueName<'_>,
+) -> Option<String> {
+    let dbus = zbus::fdo::DBusProxy::new(conn).await.ok()?;
+    let pid = dbus
+        .get_connection_unix_process_id(name.as_ref().into())
+        .await
+        .ok()?;
+    let info = std::fs::read_to_string(format!("/proc/{}/root/.flatpak-info", pid)).ok()?;
+    let mut in_application_group = false;
+    for line in info.lines() {
+        if line.starts_with('[') {
+            in_application_group = line == "[Application]";
+        } else if in_application_group && let Some(app_id) = line.strip_prefix("name=") {
+            return Some(app_id.to_string());
+        }
+    }
+    None
+}
+
+#[derive(Clone)]
+pub struct Inhibitors {
+    conn: zbus::Connection,
+    inhibitors: Arc<Mutex<Vec<Inhibitor>>>,
+    settings: Arc<Mutex<Settings>>,
+    last_cookie: Arc<AtomicU32>,
+    event_sender: EventSender,
+}
+
+impl Inhibitors {
+    pub fn new(conn: zbus::Connection, event_sender: EventSender, settings: Settings) -> Self {
+        Self {
+            conn,
+            inhibitors: Arc::new(Mutex::new(Vec::new())),
+            settings: Arc::new(Mutex::new(settings)),
+            last_cookie: Arc::new(AtomicU32::new(0)),
+            event_sender,
+        }
+    }
+
+    pub async fn add(
+        &self,
+        application_name: String,
+        reason_for_inhibit: String,
+        header: &zbus::message::Header<'_>,
+        flags: InhibitFlags,
+    ) -> u32 {
+        let cookie = self.next_cookie();
+        if let Some(sender) = header.sender() {
+            let app_id = flatpak_app_id(&self.conn, sender).await;
+            log::info!(
+                "Added inhibitor for application '{}' {:?}, app ID: {:?}, reason: {}, cookie: {}, {:?}",
+                application_name,
+                sender,
+                app_id,
+                reason_for_inhibit,
+                cookie,
+                flags
+            );
+            let source = Source::Client(sender.to_owned());
+            let now = Instant::now();
+            self.update(|inhibitors| {
+                // Inhibiting again renews the application's other inhibitors
+                for inhibitor in inhibitors.iter_mut() {
+                    if inhibitor.application_name == application_name && inhibitor.source == source
+                    {
+                        inhibitor.renewed = now;
+                    }
+                }
+                inhibitors.push(Inhibitor {
+                    cookie,
+                    application_name,
+                    reason_for_inhibit,
+                    source,
+                    app_id,
+                    flags,
+                    renewed: now,
+                })
+            })
+            .await;
+        }
+        cookie
+    }
+
+    pub async fn remove(&self, cookie: u32) {
+        let inhibitor = self
+            .update(|inhibitors| {
+                let idx = inhibitors.iter().position(|x| x.cookie == cookie)?;
+                Some(inhibitors.remove(idx))
+            })
+            .await;
+        if let Some(inhibitor) = inhibitor {
+            // Media players come and go often
+            let level = if matches!(inhibitor.source, Source::Mpris(_)) {
+                log::Level::Debug
+            } else {
+                log::Level::Info
+            };
+            log::log!(
+                level,
+                "Removed inhibitor for application '{}' {}, reason: {}, cookie: {}",
+                inhibitor.application_name,
+                inhibitor.source,
+                inhibitor.reason_for_inhibit,
+                inhibitor.cookie
+            );
+        }
+    }
+
+    // Remove an inhibitor that outlived its maximum time
+    pub async fn expire(&self, cookie: u32) {
+        log::warn!(
+            "Inhibitor with cookie {} reached its maximum time without being renewed",
+            cookie
+        );
+        self.remove(cookie).await;
+    }
+
+    // Reset the time of an application's inhibitors toward their maximum time
+    pub async fn renew(&self, application_name: &str) {
+        let now = Instant::now();
+        self.update(|inhibitors| {
+            for inhibitor in inhibitors.iter_mut() {
+                if inhibitor.application_name == application_name {
+                    inhibitor.renewed = now;
+                }
+            }
+        })
+        .await;
+    }
+
+    // Cookies of inhibitors past their maximum time
+    pub fn expired(&self, max_time: impl Fn(&str) -> Option<Duration>) -> Vec<u32> {
+        let now = Instant::now();
+        self.inhibitors
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|x| x.expiry(&max_time).is_some_and(|expiry| expiry <= now))
+            .map(|x| x.cookie)
+            .collect()
+    }
+
+    // When the next inhibitor should be dropped
+    pub fn next_expiry(&self, max_time: impl Fn(&str) -> Option<Duration>) -> Option<Instant> {
+        self.inhibitors
+            .lock()
+            .unwrap()
+            .iter()
+            .filter_map(|x| x.expiry(&max_time))
+            .min()
+    }
+
+    // Remove inhibitors added by a client that disconnected from DBus
+    async fn remove_client(&self, name: &zbus::names::UniqueName<'_>) {
+        self.update(|inhibitors| {
+            inhibitors
+                .retain(|inhibitor| !matches!(&inhibitor.source, Source::Client(x) if x == name))
+        })
+        .await;
+    }
+
+    // Replace inhibitors for logind locks with `locks`, as (who, why, flags)
+    pub async fn set_logind(&self, locks: Vec<(String, String, InhibitFlags)>) {
+        log::debug!("logind inhibitor locks: {:?}", locks);
+        let locks = locks
+            .into_iter()
+            .map(|(who, why, flags)| Inhibitor {
+                cookie: self.next_cookie(),
+                application_name: who,
+                reason_for_inhibit: why,
+                source: Source::Logind,
+                app_id: None,
+                flags,
+                renewed: Instant::now(),
+            })
+            .collect::<Vec<_>>();
+        self.update(|inhibitors| {
+            inhibitors.retain(|inhibitor| inhibitor.source != Source::Logind);
+            inhibitors.extend(locks);
+        })
+        .await;
+    }
+
+    // Add an inhibitor for an MPRIS player that started playing
+    pub async fn add_mpris(&self, identity: String, name: String) -> u32 {
+        let cookie = self.next_cookie();
+        log::debug!(
+            "Added inhibitor for media player '{}' {}, cookie: {}",
+            identity,
+            name,
+            cookie
+        );
+        self.update(|inhibitors| {
+            inhibitors.push(Inhibitor {
+                cookie,
+                application_name: identity,
+                reason_for_inhibit: "Playing media".to_string(),
+                source: Source::Mpris(name),
+                app_id: None,
+                flags: InhibitFlags::SUSPEND,
+                renewed: Instant::now(),
+            })
+        })
+        .await;
+        cookie
+    }
+
+    fn next_cookie(&self) -> u32 {
+        self.last_cookie.fetch_add(1, Ordering::Relaxed) + 1
+    }
+
+    pub async fn set_settings(&self, settings: Settings) {
+        if *self.settings.lock().unwrap() == settings {
+            return;
+        }
+        // Updates what is inhibited
+        self.update(|_| *self.settings.lock().unwrap() = settings)
+            .await;
+    }
+
+    // Modify the inhibitors, and notify if that changes what is inhibited
+    async fn update<T>(&self, f: impl FnOnce(&mut Vec<Inhibitor>) -> T) -> T {
+        let (res, old_flags, new_flags) = {
+            let mut inhibitors = self.inhibitors.lock().unwrap();
+            let old_flags = flags(&inhibitors, &self.settings.lock().unwrap());
+            let res = f(&mut inhibitors);
+            (
+                res,
+                old_flags,
+                flags(&inhibitors, &self.settings.lock().unwrap()),
+            )
+        };
+        let _ = self.event_sender.send(Event::InhibitorsChanged);
+        if new_flags != old_flags {
+            let _ = self.event_sender.send(Event::Inhibit(new_flags));
+        }
+        let had_inhibit = old_flags != InhibitFlags::default();
+        let has_inhibit = new_flags != InhibitFlags::default();
+        if has_inhibit != had_inhibit
+            && let Err(err) =
+                freedesktop_screensaver::emit_has_inhibit_changed(&self.conn, has_inhibit).await
+        {
+            log::error!("failed to emit `HasInhibitChanged`: {}", err);
+        }
+        res
+    }
+
+    // Whether any inhibitor is honored, after applying settings
+    pub fn has_inhibit(&self) -> bool {
+        let inhibitors = self.inhibitors.lock().unwrap();
+        flags(&inhibitors, &self.settings.lock().unwrap()) != InhibitFlags::default()
+    }
+
+    // Application name, reason, and source of each inhibitor that is honored
+    pub fn list(&self) -> Vec<(String, String, String)> {
+        let inhibitors = self.inhibitors.lock().unwrap();
+        let settings = self.settings.lock().unwrap();
+        inhibitors
+            .iter()
+            .filter(|x| x.effective_flags(&settings) != InhibitFlags::default())
+            .map(|x| {
+                (
+                    x.application_name.clone(),
+                    x.reason_for_inhibit.clone(),
+                    x.source.to_string(),
+                )
+            })
+            .collect()
+    }
+
+    // If a client disconnects from DBus, remove any inhibitors it has added.
+    pub async fn watch_clients(&self) -> zbus::Result<()> {
+        let dbus = zbus::fdo::DBusProxy::new(&self.conn).await?;
+        let mut name_owner_stream = dbus.receive_name_owner_changed().await?;
+        while let Some(event) = name_owner_stream.next().await {
+            let args = event.args()?;
+            if args.new_owner.is_none()
+                && let zbus::names::BusName::Unique(name) = args.name
+            {
+                self.remove_client(&name).await;
+            }
+        }
+        Ok(())
+    }
+}
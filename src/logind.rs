@@ -0,0 +1,183 @@
+// https://www.freedesktop.org/software/systemd/man/latest/org.freedesktop.login1.html
+
+use futures_lite::StreamExt;
+
+use crate::{
+    Event, EventSender,
+    inhibitor::{InhibitFlags, Inhibitors},
+};
+
+// (what, who, why, mode, uid, pid)
+type InhibitorLock = (String, String, String, String, u32, u32);
+
+#[zbus::proxy(
+    interface = "org.freedesktop.login1.Manager",
+    default_service = "org.freedesktop.login1",
+    default_path = "/org/freedesktop/login1"
+)]
+trait Manager {
+    fn inhibit(
+        &self,
+        what: &str,
+        who: &str,
+        why: &str,
+        mode: &str,
+    ) -> zbus::Result<zbus::zvariant::OwnedFd>;
+
+    fn list_inhibitors(&self) -> zbus::Result<Vec<InhibitorLock>>;
+
+    // Colon-separated list of what block mode locks inhibit
+    #[zbus(property)]
+    fn block_inhibited(&self) -> zbus::Result<String>;
+
+    fn get_session(&self, session_id: &str) -> zbus::Result<zbus::zvariant::OwnedObjectPath>;
+
+    #[zbus(signal)]
+    fn prepare_for_sleep(&self, start: bool) -> zbus::Result<()>;
+}
+
+// Method calls on the `auto` path apply to the caller's session. Signals,
+// including property changes, are sent on the session's real path.
+#[zbus::proxy(
+    interface = "org.freedesktop.login1.Session",
+    default_service = "org.freedesktop.login1",
+    default_path = "/org/freedesktop/login1/session/auto"
+)]
+trait Session {
+    fn set_idle_hint(&self, idle: bool) -> zbus::Result<()>;
+
+    // Set by the screen locker
+    #[zbus(property)]
+    fn locked_hint(&self) -> zbus::Result<bool>;
+
+    #[zbus(signal)]
+    fn lock(&self) -> zbus::Result<()>;
+}
+
+// What a logind inhibitor lock prevents, if anything handled here
+fn lock_flags(what: &str) -> Option<InhibitFlags> {
+    let mut flags = None;
+    for what in what.split(':') {
+        let new_flags = match what {
+            // Also blocks suspending on idle, as logind documents
+            "idle" => InhibitFlags::IDLE,
+            "sleep" => InhibitFlags::SUSPEND,
+            _ => continue,
+        };
+        flags = Some(flags.unwrap_or_default() | new_flags);
+    }
+    flags
+}
+
+async fn update_inhibitors(
+    manager: &ManagerProxy<'_>,
+    inhibitors: &Inhibitors,
+) -> zbus::Result<()> {
+    let mut locks = Vec::new();
+    if lock_flags(&manager.block_inhibited().await?).is_some() {
+        for (what, who, why, mode, _uid, _pid) in manager.list_inhibitors().await? {
+            if mode == "block"
+                && let Some(flags) = lock_flags(&what)
+            {
+                locks.push((who, why, flags));
+            }
+        }
+    }
+    inhibitors.set_logind(locks).await;
+    Ok(())
+}
+
+// Treat logind `idle` and `sleep` block locks as inhibitors
+pub async fn receive_inhibitors_task(inhibitors: Inhibitors) -> zbus::Result<()> {
+    let connection = zbus::Connection::system().await?;
+    let manager = ManagerProxy::builder(&connection)
+        .cache_properties(zbus::proxy::CacheProperties::No)
+        .build()
+        .await?;
+    // logind signals `BlockInhibited` as changed whenever a block lock is
+    // taken or released, even if the value is the same, so the list of locks
+    // has to be read again each time
+    let properties = zbus::fdo::PropertiesProxy::builder(&connection)
+        .destination(manager.inner().destination().to_owned())?
+        .path(manager.inner().path().to_owned())?
+        .build()
+        .await?;
+    let mut stream = properties.receive_properties_changed().await?;
+    update_inhibitors(&manager, &inhibitors).await?;
+    while let Some(signal) = stream.next().await {
+        let args = signal.args()?;
+        if args.interface_name == manager.inner().interface().as_ref()
+            && (args.changed_properties.contains_key("BlockInhibited")
+                || args.invalidated_properties.contains(&"BlockInhibited"))
+        {
+            update_inhibitors(&manager, &inhibitors).await?;
+        }
+    }
+    Ok(())
+}
+
+// Delay sleep until the screen is blanked and locked
+async fn take_sleep_lock(manager: &ManagerProxy<'_>) -> Option<zbus::zvariant::OwnedFd> {
+    match manager
+        .inhibit("sleep", "cosmic-idle", "Lock screen before sleep", "delay")
+        .await
+    {
+        Ok(fd) => Some(fd),
+        Err(err) => {
+            log::error!("failed to take sleep delay lock: {}", err);
+            None
+        }
+    }
+}
+
+pub async fn receive_sleep_task(sender: EventSender) -> zbus::Result<()> {
+    let connection = zbus::Connection::system().await?;
+    let manager = ManagerProxy::new(&connection).await?;
+    let mut stream = manager.receive_prepare_for_sleep().await?;
+    let mut sleep_lock = take_sleep_lock(&manager).await;
+    while let Some(signal) = stream.next().await {
+        if signal.args()?.start {
+            // Released by `State` once it has locked
+            let _ = sender.send(Event::PrepareForSleep(sleep_lock.take()));
+        } else {
+            let _ = sender.send(Event::ResumeFromSleep);
+            if sleep_lock.is_none() {
+                sleep_lock = take_sleep_lock(&manager).await;
+            }
+        }
+    }
+    Ok(())
+}
+
+pub async fn set_idle_hint(conn: &zbus::Connection, idle: bool) -> zbus::Result<()> {
+    SessionProxy::new(conn).await?.set_idle_hint(idle).await
+}
+
+// Follow the session's `LockedHint`, which the screen locker sets once it has
+// locked, and clears once unlocked. Also follow `Lock` requests, such as from
+// `loginctl lock-session`, which the locker handles itself. `Unlock` needs
+// nothing here, since `LockedHint` is cleared.
+pub async fn receive_lock_task(sender: EventSender) -> zbus::Result<()> {
+    let connection = zbus::Connection::system().await?;
+    let manager = ManagerProxy::new(&connection).await?;
+    let session = SessionProxy::builder(&connection)
+        .path(manager.get_session("auto").await?)?
+        .build()
+        .await?;
+    let mut locked_hint_stream = session.receive_locked_hint_changed().await;
+    let mut lock_stream = session.receive_lock().await?;
+    let locked_hint = async {
+        while let Some(event) = locked_hint_stream.next().await {
+            let _ = sender.send(Event::SessionLocked(event.get().await?));
+        }
+        Ok::<_, zbus::Error>(())
+    };
+    let lock = async {
+        while lock_stream.next().await.is_some() {
+            let _ = sender.send(Event::LockRequested);
+        }
+        Ok(())
+    };
+    futures_lite::future::try_zip(locked_hint, lock).await?;
+    Ok(())
+}
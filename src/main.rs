@@ -3,10 +3,18 @@
 use calloop::{EventLoop, channel, timer};
 use calloop_wayland_source::WaylandSource;
 use cosmic_config::{CosmicConfigEntry, calloop::ConfigWatchSource};
-use cosmic_idle_config::CosmicIdleConfig;
+use cosmic_idle_config::{
+    CosmicIdleConfig, CriticalBatteryAction, FullscreenInhibit, SuspendAction,
+};
+use cosmic_protocols::toplevel_info::v1::client::zcosmic_toplevel_info_v1;
 use cosmic_settings_config::shortcuts;
 use futures_lite::stream::StreamExt;
-use std::{process::Command, time::Duration};
+use std::{
+    collections::HashMap,
+    process::Command,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
 use upower_dbus::UPowerProxy;
 use wayland_client::{
     Connection, Dispatch, Proxy, QueueHandle, delegate_noop,
@@ -14,9 +22,13 @@ use wayland_client::{
     protocol::{wl_compositor, wl_output, wl_registry, wl_seat},
 };
 use wayland_protocols::{
-    ext::idle_notify::v1::client::{ext_idle_notification_v1, ext_idle_notifier_v1},
+    ext::{
+        foreign_toplevel_list::v1::client::ext_foreign_toplevel_list_v1,
+        idle_notify::v1::client::{ext_idle_notification_v1, ext_idle_notifier_v1},
+    },
     wp::{
         single_pixel_buffer::v1::client::wp_single_pixel_buffer_manager_v1,
+        tablet::zv2::client::zwp_tablet_manager_v2,
         viewporter::client::{wp_viewport, wp_viewporter},
     },
 };
@@ -25,42 +37,201 @@ use wayland_protocols_wlr::{
     output_power_management::v1::client::{zwlr_output_power_manager_v1, zwlr_output_power_v1},
 };
 
+mod control;
 mod fade_black;
 use fade_black::FadeBlackSurface;
 mod freedesktop_screensaver;
+mod inhibitor;
+use inhibitor::{InhibitFlags, Inhibitors};
+mod logind;
+mod mpris;
+mod portal_inhibit;
+mod seat;
+use seat::Seat;
+mod toplevel_info;
+use toplevel_info::Toplevel;
 
-// Delay between screen off and locking
-const LOCK_SCREEN_DELAY: Duration = Duration::from_millis(500);
+// Duration of the fade back in when the user is active again
+const FADE_IN_TIME: Duration = Duration::from_millis(250);
+// Timeout of the notification used to track when the user was last active, in ms
+const ACTIVITY_TIMEOUT: u32 = 1000;
+// How long to hold the logind sleep delay lock, waiting for the session to lock
+const SLEEP_LOCK_TIMEOUT: Duration = Duration::from_secs(3);
 
 #[derive(Debug)]
 enum Event {
     OnBattery(bool),
-    ScreensaverInhibit(bool),
+    BatteryLevel {
+        percentage: f64,
+        // In seconds, or 0 if unknown
+        time_to_empty: i64,
+    },
+    Inhibit(InhibitFlags),
+    // Inhibitors were added, removed, or renewed
+    InhibitorsChanged,
+    SetScreensaverActive(bool),
+    Lock,
+    SimulateUserActivity,
+    // Inhibit idle and suspend for a time, or stop pausing if zero
+    PauseFor(Duration),
+    Suspend,
+    // With the logind sleep delay lock, to release once locked
+    PrepareForSleep(Option<zbus::zvariant::OwnedFd>),
+    ResumeFromSleep,
+    // logind `LockedHint` changed
+    SessionLocked(bool),
+    // logind `Lock` signal, handled by the screen locker
+    LockRequested,
 }
 
 type EventSender = channel::Sender<Event>;
 
-struct IdleNotification {
+struct SeatIdleNotification {
+    seat: wl_seat::WlSeat,
     notification: ext_idle_notification_v1::ExtIdleNotificationV1,
-    time: u32,
+    is_idle: bool,
 }
 
-impl IdleNotification {
-    fn new(inner: &StateInner, time: u32) -> Self {
-        let notification =
-            inner
-                .idle_notifier
-                .get_idle_notification(time, &inner.seat, &inner.qh, ());
-        Self { notification, time }
+impl SeatIdleNotification {
+    fn new(inner: &StateInner, seat: &wl_seat::WlSeat, timeout: u32) -> Self {
+        let notification = inner
+            .idle_notifier
+            .get_idle_notification(timeout, seat, &inner.qh, ());
+        Self {
+            seat: seat.clone(),
+            notification,
+            is_idle: false,
+        }
     }
 }
 
-impl Drop for IdleNotification {
+impl Drop for SeatIdleNotification {
     fn drop(&mut self) {
         self.notification.destroy();
     }
 }
 
+struct IdleNotification {
+    // One for each seat
+    notifications: Vec<SeatIdleNotification>,
+    time: u32,
+    // Timeout the notification was created with. Less than `time` if the user
+    // was already idle at the time.
+    timeout: u32,
+    // Idle on the primary seat, or all seats
+    is_idle: bool,
+}
+
+impl IdleNotification {
+    // `idle_time` is how long the user has already been idle, which counts
+    // toward `time`.
+    fn new(inner: &StateInner, time: u32, idle_time: Duration) -> Self {
+        let idle_time = u32::try_from(idle_time.as_millis()).unwrap_or(u32::MAX);
+        let timeout = time.saturating_sub(idle_time).max(1);
+        let notifications = inner
+            .seats
+            .iter()
+            .map(|seat| SeatIdleNotification::new(inner, &seat.seat, timeout))
+            .collect();
+        Self {
+            notifications,
+            time,
+            timeout,
+            is_idle: false,
+        }
+    }
+
+    fn add_seat(&mut self, inner: &StateInner, seat: &wl_seat::WlSeat, idle_time: Duration) {
+        let idle_time = u32::try_from(idle_time.as_millis()).unwrap_or(u32::MAX);
+        let timeout = self.time.saturating_sub(idle_time).max(1);
+        self.timeout = self.timeout.min(timeout);
+        self.notifications
+            .push(SeatIdleNotification::new(inner, seat, timeout));
+    }
+
+    fn remove_seat(&mut self, seat: &wl_seat::WlSeat) {
+        self.notifications.retain(|x| &x.seat != seat);
+    }
+
+    fn seats_idle(&self, inner: &StateInner, primary_seat: Option<&str>) -> bool {
+        let primary_notification = primary_seat
+            .and_then(|name| inner.seats.iter().find(|x| x.name.as_deref() == Some(name)))
+            .and_then(|primary| self.notifications.iter().find(|x| x.seat == primary.seat));
+        if let Some(x) = primary_notification {
+            x.is_idle
+        } else {
+            !self.notifications.is_empty() && self.notifications.iter().all(|x| x.is_idle)
+        }
+    }
+
+    // Handle `idled` or `resumed` for a seat. Returns the new idle state, if
+    // the notification is for this seat and the idle state of the seats has
+    // changed.
+    fn seat_event(
+        &mut self,
+        inner: &StateInner,
+        primary_seat: Option<&str>,
+        notification: &ext_idle_notification_v1::ExtIdleNotificationV1,
+        is_idle: bool,
+    ) -> Option<bool> {
+        let x = self
+            .notifications
+            .iter_mut()
+            .find(|x| &x.notification == notification)?;
+        x.is_idle = is_idle;
+        self.seats_idle_changed(inner, primary_seat)
+    }
+
+    // Returns the idle state of the seats, if it differs from `is_idle`
+    fn seats_idle_changed(&self, inner: &StateInner, primary_seat: Option<&str>) -> Option<bool> {
+        let is_idle = self.seats_idle(inner, primary_seat);
+        (is_idle != self.is_idle).then_some(is_idle)
+    }
+
+    // Update for a new idle time, or none. Returns `true` if the notification was
+    // recreated, and is no longer idle.
+    //
+    // A notification that is already idle for longer than the new time is kept,
+    // and re-armed with the new time once the user is active.
+    fn update(
+        notification: &mut Option<Self>,
+        inner: &StateInner,
+        time: Option<u32>,
+        idle_time: Duration,
+    ) -> bool {
+        match (notification.as_mut(), time) {
+            (None, None) => false,
+            (Some(x), Some(time)) if x.time == time => false,
+            (Some(x), Some(time))
+                if x.is_idle && Duration::from_millis(time.into()) <= idle_time =>
+            {
+                x.time = time;
+                false
+            }
+            _ => {
+                // With a shortened timeout if already idle, re-armed with the
+                // full time by `State::rearm_notifications`
+                *notification = time.map(|time| Self::new(inner, time, idle_time));
+                true
+            }
+        }
+    }
+
+    fn set_idle(&mut self, inner: &StateInner, is_idle: bool) {
+        self.is_idle = is_idle;
+        if !is_idle {
+            self.rearm(inner);
+        }
+    }
+
+    // Once the user is active, the full time applies again
+    fn rearm(&mut self, inner: &StateInner) {
+        if self.timeout != self.time {
+            *self = Self::new(inner, self.time, Duration::ZERO);
+        }
+    }
+}
+
 async fn receive_battery_task(sender: EventSender) -> zbus::Result<()> {
     let connection = zbus::Connection::system().await?;
     let upower = UPowerProxy::new(&connection).await?;
@@ -71,6 +242,45 @@ async fn receive_battery_task(sender: EventSender) -> zbus::Result<()> {
     Ok(())
 }
 
+async fn receive_battery_level_task(sender: EventSender) -> zbus::Result<()> {
+    let connection = zbus::Connection::system().await?;
+    let upower = UPowerProxy::new(&connection).await?;
+    let device = upower.get_display_device().await?;
+    let percentage_stream = device.receive_percentage_changed().await.map(|_| ());
+    let time_to_empty_stream = device.receive_time_to_empty_changed().await.map(|_| ());
+    let mut stream = percentage_stream.or(time_to_empty_stream);
+    while stream.next().await.is_some() {
+        let _ = sender.send(Event::BatteryLevel {
+            percentage: device.percentage().await?,
+            time_to_empty: device.time_to_empty().await?,
+        });
+    }
+    Ok(())
+}
+
+async fn send_notification(summary: &str, body: &str) -> zbus::Result<()> {
+    let connection = zbus::Connection::session().await?;
+    connection
+        .call_method(
+            Some("org.freedesktop.Notifications"),
+            "/org/freedesktop/Notifications",
+            Some("org.freedesktop.Notifications"),
+            "Notify",
+            &(
+                "cosmic-idle",
+                0u32,
+                "battery-level-0-symbolic",
+                summary,
+                body,
+                Vec::<&str>::new(),
+                HashMap::<&str, zbus::zvariant::Value>::new(),
+                -1i32,
+            ),
+        )
+        .await?;
+    Ok(())
+}
+
 #[derive(Debug)]
 struct Output {
     output: wl_output::WlOutput,
@@ -81,6 +291,7 @@ struct Output {
 
 // Immutate references to globals, needed for calls
 struct StateInner {
+    connection: Connection,
     registry: wl_registry::WlRegistry,
     output_power_manager: zwlr_output_power_manager_v1::ZwlrOutputPowerManagerV1,
     compositor: wl_compositor::WlCompositor,
@@ -88,7 +299,8 @@ struct StateInner {
     viewporter: wp_viewporter::WpViewporter,
     single_pixel_buffer_manager: wp_single_pixel_buffer_manager_v1::WpSinglePixelBufferManagerV1,
     idle_notifier: ext_idle_notifier_v1::ExtIdleNotifierV1,
-    seat: wl_seat::WlSeat,
+    tablet_manager: Option<zwp_tablet_manager_v2::ZwpTabletManagerV2>,
+    seats: Vec<Seat>,
     qh: QueueHandle<State>,
 }
 
@@ -96,12 +308,54 @@ struct State {
     inner: StateInner,
     outputs: Vec<Output>,
     conf: CosmicIdleConfig,
+    dim_idle_notification: Option<IdleNotification>,
     screen_off_idle_notification: Option<IdleNotification>,
+    lock_idle_notification: Option<IdleNotification>,
     suspend_idle_notification: Option<IdleNotification>,
+    activity_idle_notification: IdleNotification,
+    // Turns the screen off right away, until the user is active
+    force_screen_off_notification: Option<IdleNotification>,
+    // When the user was last active, if currently idle
+    idle_since: Option<Instant>,
+    // When the screen started fading out, if it is fading or off
+    screen_off_since: Option<Instant>,
+    screensaver_status: Arc<Mutex<freedesktop_screensaver::Status>>,
+    // Timer to lock the screen after it turns off, if not cancelled by input
+    lock_timer: Option<calloop::RegistrationToken>,
+    // Session `LockedHint` from logind
+    locked: bool,
+    // logind sleep delay lock, held until the session locks before sleep
+    sleep_lock: Option<zbus::zvariant::OwnedFd>,
+    // Timer to release `sleep_lock` if the session doesn't lock in time
+    sleep_lock_timer: Option<calloop::RegistrationToken>,
+    stage: control::Stage,
+    control_status: Arc<Mutex<control::Status>>,
+    // Timer to end a pause requested through the control interface
+    pause_timer: Option<calloop::RegistrationToken>,
     on_battery: bool,
-    screensaver_inhibit: bool,
+    battery_percentage: f64,
+    // In seconds, or 0 if unknown
+    battery_time_to_empty: i64,
+    // Set once the critical battery action has run, until no longer critical
+    battery_critical: bool,
+    inhibit: InhibitFlags,
+    inhibitors: Option<Inhibitors>,
+    // Bound if the compositor supports it
+    toplevel_info: Option<zcosmic_toplevel_info_v1::ZcosmicToplevelInfoV1>,
+    toplevels: Vec<Toplevel>,
+    // Bus names of media players paused when the screen turned off or locked
+    paused_players: Arc<Mutex<Vec<String>>>,
+    // Set once media players are watched for `media_playback_inhibit`
+    watching_players: bool,
+    // Set if a fullscreen toplevel inhibits idle stages
+    fullscreen_toplevel: bool,
+    // Timer to drop the next inhibitor to reach its maximum time
+    inhibitor_expiry_timer: Option<calloop::RegistrationToken>,
     system_actions: shortcuts::SystemActions,
     loop_handle: calloop::LoopHandle<'static, Self>,
+    scheduler: calloop::futures::Scheduler<()>,
+    session_bus: Option<zbus::Connection>,
+    system_bus: Option<zbus::Connection>,
 }
 
 fn run_command(command: String) {
@@ -140,14 +394,297 @@ impl State {
         });
     }
 
-    fn update_screen_off_idle(&mut self, is_idle: bool) {
+    fn add_seat_global(&mut self, global_name: u32, version: u32) {
+        let seat = self
+            .inner
+            .registry
+            .bind(global_name, version.min(5), &self.inner.qh, ());
+        let idle_time = self.idle_time();
+        for x in [
+            &mut self.force_screen_off_notification,
+            &mut self.dim_idle_notification,
+            &mut self.screen_off_idle_notification,
+            &mut self.lock_idle_notification,
+            &mut self.suspend_idle_notification,
+        ]
+        .into_iter()
+        .flatten()
+        .chain([&mut self.activity_idle_notification])
+        {
+            x.add_seat(&self.inner, &seat, idle_time);
+        }
+        let seat = Seat::new(
+            seat,
+            global_name,
+            self.inner.tablet_manager.as_ref(),
+            &self.inner.qh,
+        );
+        self.inner.seats.push(seat);
+    }
+
+    fn remove_seat_global(&mut self, global_name: u32) {
+        let Some(idx) = self
+            .inner
+            .seats
+            .iter()
+            .position(|x| x.global_name == global_name)
+        else {
+            return;
+        };
+        let seat = self.inner.seats.remove(idx);
+        for x in [
+            &mut self.force_screen_off_notification,
+            &mut self.dim_idle_notification,
+            &mut self.screen_off_idle_notification,
+            &mut self.lock_idle_notification,
+            &mut self.suspend_idle_notification,
+        ]
+        .into_iter()
+        .flatten()
+        .chain([&mut self.activity_idle_notification])
+        {
+            x.remove_seat(&seat.seat);
+        }
+        // Remaining seats may already be idle
+        self.update_seats_idle();
+    }
+
+    // Re-evaluate the idle state of each notification from its seats, after a
+    // seat is removed or the primary seat changes
+    fn update_seats_idle(&mut self) {
+        let primary_seat = self.conf.primary_seat.clone();
+        let primary_seat = primary_seat.as_deref();
+        if let Some(is_idle) = self
+            .activity_idle_notification
+            .seats_idle_changed(&self.inner, primary_seat)
+        {
+            self.set_activity_idle(is_idle);
+        }
+        if let Some(x) = &self.force_screen_off_notification
+            && let Some(is_idle) = x.seats_idle_changed(&self.inner, primary_seat)
+        {
+            self.set_force_screen_off_idle(is_idle);
+        }
+        if let Some(x) = &mut self.dim_idle_notification
+            && let Some(is_idle) = x.seats_idle_changed(&self.inner, primary_seat)
+        {
+            x.set_idle(&self.inner, is_idle);
+            self.update_dim_idle(is_idle);
+        }
+        if let Some(x) = &mut self.screen_off_idle_notification
+            && let Some(is_idle) = x.seats_idle_changed(&self.inner, primary_seat)
+        {
+            x.set_idle(&self.inner, is_idle);
+            self.update_screen_off_idle(is_idle);
+        }
+        if let Some(x) = &mut self.lock_idle_notification
+            && let Some(is_idle) = x.seats_idle_changed(&self.inner, primary_seat)
+        {
+            x.set_idle(&self.inner, is_idle);
+            self.update_lock_idle(is_idle);
+        }
+        if let Some(x) = &mut self.suspend_idle_notification
+            && let Some(is_idle) = x.seats_idle_changed(&self.inner, primary_seat)
+        {
+            x.set_idle(&self.inner, is_idle);
+            self.update_suspend_idle(is_idle);
+        }
+        self.update_stage();
+    }
+
+    fn set_activity_idle(&mut self, is_idle: bool) {
+        self.activity_idle_notification.is_idle = is_idle;
+        self.set_idle_since(if is_idle {
+            Instant::now().checked_sub(Duration::from_millis(ACTIVITY_TIMEOUT.into()))
+        } else {
+            None
+        });
+        if !is_idle {
+            self.rearm_notifications();
+            self.resume_media();
+        }
+    }
+
+    fn set_force_screen_off_idle(&mut self, is_idle: bool) {
+        if let Some(x) = &mut self.force_screen_off_notification {
+            x.is_idle = is_idle;
+        }
+        if !is_idle {
+            self.force_screen_off_notification = None;
+        }
+        self.update_screen_off_idle(is_idle);
+    }
+
+    // Re-arm notifications created with a shortened timeout, that the user
+    // became active before. Ones that are idle are re-armed on `resumed`.
+    fn rearm_notifications(&mut self) {
+        for x in [
+            &mut self.dim_idle_notification,
+            &mut self.screen_off_idle_notification,
+            &mut self.lock_idle_notification,
+            &mut self.suspend_idle_notification,
+        ]
+        .into_iter()
+        .flatten()
+        {
+            if !x.is_idle {
+                x.rearm(&self.inner);
+            }
+        }
+    }
+
+    fn is_dim_idle(&self) -> bool {
+        self.dim_idle_notification
+            .as_ref()
+            .is_some_and(|x| x.is_idle)
+    }
+
+    fn is_screen_off_idle(&self) -> bool {
+        [
+            &self.screen_off_idle_notification,
+            &self.force_screen_off_notification,
+        ]
+        .into_iter()
+        .flatten()
+        .any(|x| x.is_idle)
+    }
+
+    fn is_lock_idle(&self) -> bool {
+        self.lock_idle_notification
+            .as_ref()
+            .is_some_and(|x| x.is_idle)
+    }
+
+    // Fade every output to `alpha`, creating fade surfaces as needed
+    fn fade_outputs(&mut self, alpha: f64, duration: Duration) {
         for output in &mut self.outputs {
-            if is_idle {
-                output.fade_surface = Some(FadeBlackSurface::new(&self.inner, &output.output));
+            if let Some(fade_surface) = &mut output.fade_surface {
+                fade_surface.fade_to(&self.inner, &self.conf, alpha, duration);
+            } else if alpha > 0. {
+                output.fade_surface = Some(FadeBlackSurface::new(
+                    &self.inner,
+                    &output.output,
+                    alpha,
+                    duration,
+                ));
+            }
+        }
+    }
+
+    fn fade_out_duration(&self) -> Duration {
+        Duration::from_millis(self.conf.fade_time.into())
+    }
+
+    fn fade_in_duration(&self) -> Duration {
+        FADE_IN_TIME.min(self.fade_out_duration())
+    }
+
+    fn update_dim_idle(&mut self, is_idle: bool) {
+        // Screen off takes precedence over dimming
+        if self.is_screen_off_idle() {
+            return;
+        }
+        if is_idle {
+            let dim_opacity = f64::from(self.conf.dim_opacity.clamp(0., 1.));
+            self.fade_outputs(dim_opacity, self.fade_out_duration());
+        } else {
+            self.fade_outputs(0., self.fade_in_duration());
+        }
+    }
+
+    fn redraw_fade_surfaces(&self) {
+        for output in &self.outputs {
+            if let Some(fade_surface) = &output.fade_surface {
+                fade_surface.redraw(&self.inner, &self.conf);
+            }
+        }
+    }
+
+    fn update_screen_off_idle(&mut self, is_idle: bool) {
+        if is_idle {
+            if self.screen_off_since.is_some() {
+                return;
+            }
+            self.set_screen_off_since(Some(Instant::now()));
+            if self.conf.fade_time == 0 {
+                self.fade_done();
             } else {
-                output.fade_surface = None;
+                // Continues from the current alpha, if dimmed or fading in
+                self.fade_outputs(1., self.fade_out_duration());
+            }
+        } else {
+            // Screen off was also forced, or vice versa
+            if self.is_screen_off_idle() {
+                return;
+            }
+            self.set_screen_off_since(None);
+            if let Some(token) = self.lock_timer.take() {
+                self.loop_handle.remove(token);
+            }
+            for output in &self.outputs {
                 output.output_power.set_mode(zwlr_output_power_v1::Mode::On);
             }
+            let alpha = if self.is_dim_idle() {
+                f64::from(self.conf.dim_opacity.clamp(0., 1.))
+            } else {
+                0.
+            };
+            self.fade_outputs(alpha, self.fade_in_duration());
+        }
+    }
+
+    fn set_screen_off_since(&mut self, screen_off_since: Option<Instant>) {
+        let changed = self.screen_off_since.is_some() != screen_off_since.is_some();
+        self.screen_off_since = screen_off_since;
+        self.screensaver_status.lock().unwrap().active_since = screen_off_since;
+        if changed && let Some(conn) = self.system_bus.clone() {
+            let idle = screen_off_since.is_some();
+            self.spawn(async move {
+                if let Err(err) = logind::set_idle_hint(&conn, idle).await {
+                    log::error!("failed to set logind idle hint: {}", err);
+                }
+            });
+        }
+        if changed && let Some(conn) = self.session_bus.clone() {
+            let active = screen_off_since.is_some();
+            self.spawn(async move {
+                if let Err(err) = freedesktop_screensaver::emit_active_changed(&conn, active).await
+                {
+                    log::error!("failed to emit screensaver `ActiveChanged`: {}", err);
+                }
+            });
+        }
+    }
+
+    fn set_idle_since(&mut self, idle_since: Option<Instant>) {
+        self.idle_since = idle_since;
+        self.screensaver_status.lock().unwrap().idle_since = idle_since;
+    }
+
+    // Turn the screen off now, until the user is active again
+    fn force_screen_off(&mut self) {
+        self.force_screen_off_notification =
+            Some(IdleNotification::new(&self.inner, 1, Duration::ZERO));
+    }
+
+    // Restart idle timers, as if the user was active
+    fn reset_idle(&mut self) {
+        self.set_idle_since(None);
+        self.activity_idle_notification =
+            IdleNotification::new(&self.inner, ACTIVITY_TIMEOUT, Duration::ZERO);
+        self.force_screen_off_notification = None;
+        self.dim_idle_notification = None;
+        self.screen_off_idle_notification = None;
+        self.lock_idle_notification = None;
+        self.suspend_idle_notification = None;
+        self.update_screen_off_idle(false);
+        self.update_dim_idle(false);
+        self.recreate_notifications();
+    }
+
+    fn spawn<F: Future<Output = ()> + 'static>(&self, future: F) {
+        if let Err(err) = self.scheduler.schedule(future) {
+            log::error!("failed to schedule future: {}", err);
         }
     }
 
@@ -159,14 +696,113 @@ impl State {
                 .set_mode(zwlr_output_power_v1::Mode::Off);
             output.fade_surface = None;
         }
+        self.update_stage();
+        self.pause_media();
 
-        let timer = timer::Timer::from_duration(LOCK_SCREEN_DELAY);
-        self.loop_handle
+        // Don't lock again if already locked, or the lock idle time has passed
+        if !self.conf.lock_on_screen_off || self.locked || self.is_lock_idle() {
+            return;
+        }
+
+        let timer =
+            timer::Timer::from_duration(Duration::from_millis(self.conf.lock_grace_time.into()));
+        let token = self
+            .loop_handle
             .insert_source(timer, |_, _, state| {
+                state.lock_timer = None;
                 state.lock_screen();
                 timer::TimeoutAction::Drop
             })
             .unwrap();
+        if let Some(token) = self.lock_timer.replace(token) {
+            self.loop_handle.remove(token);
+        }
+    }
+
+    // Pause media players, if enabled, to resume when the user is active
+    fn pause_media(&self) {
+        if !self.conf.pause_media {
+            return;
+        }
+        let Some(conn) = self.session_bus.clone() else {
+            return;
+        };
+        let paused_players = self.paused_players.clone();
+        self.spawn(async move {
+            match mpris::pause_players(&conn).await {
+                Ok(names) => {
+                    let mut paused_players = paused_players.lock().unwrap();
+                    for name in names {
+                        if !paused_players.contains(&name) {
+                            paused_players.push(name);
+                        }
+                    }
+                }
+                Err(err) => log::error!("failed to pause media players: {}", err),
+            }
+        });
+    }
+
+    // Resume players paused by `pause_media`, once the session is unlocked
+    fn resume_media(&self) {
+        if self.locked {
+            return;
+        }
+        let names = std::mem::take(&mut *self.paused_players.lock().unwrap());
+        if !self.conf.resume_media || names.is_empty() {
+            return;
+        }
+        if let Some(conn) = self.session_bus.clone() {
+            self.spawn(async move {
+                mpris::play_players(&conn, names).await;
+            });
+        }
+    }
+
+    // Turn the screen off and lock right away, before the system sleeps. The
+    // sleep delay lock is held until the session has locked.
+    fn prepare_for_sleep(&mut self, sleep_lock: Option<zbus::zvariant::OwnedFd>) {
+        if self.screen_off_since.is_none() {
+            self.set_screen_off_since(Some(Instant::now()));
+        }
+        self.fade_done();
+        if let Some(token) = self.lock_timer.take() {
+            self.loop_handle.remove(token);
+        }
+        // Unless locking is disabled entirely
+        let lock_enabled = self.conf.lock_on_screen_off || self.conf.lock_time.is_some();
+        let lock = lock_enabled && !self.locked;
+        if lock {
+            self.lock_screen();
+        }
+        // Turn outputs off before sleeping
+        if let Err(err) = self.inner.connection.flush() {
+            log::error!("failed to flush wayland connection: {}", err);
+        }
+        if !lock || sleep_lock.is_none() {
+            return;
+        }
+
+        self.release_sleep_lock();
+        self.sleep_lock = sleep_lock;
+        let timer = timer::Timer::from_duration(SLEEP_LOCK_TIMEOUT);
+        let token = self
+            .loop_handle
+            .insert_source(timer, |_, _, state| {
+                state.sleep_lock_timer = None;
+                log::warn!("session didn't lock before sleep, releasing sleep delay lock");
+                state.release_sleep_lock();
+                timer::TimeoutAction::Drop
+            })
+            .unwrap();
+        self.sleep_lock_timer = Some(token);
+    }
+
+    fn release_sleep_lock(&mut self) {
+        if let Some(token) = self.sleep_lock_timer.take() {
+            self.loop_handle.remove(token);
+        }
+        self.sleep_lock = None;
     }
 
     fn lock_screen(&self) {
@@ -175,34 +811,190 @@ impl State {
             .get(&shortcuts::action::System::LockScreen)
             .map_or("loginctl lock-session", |s| s.as_str());
         crate::run_command(command.to_string());
+        self.pause_media();
     }
 
-    fn update_suspend_idle(&mut self, is_idle: bool) {
-        if is_idle {
-            let command = self
+    fn update_lock_idle(&mut self, is_idle: bool) {
+        // Such as when the screen already locked after turning off
+        if is_idle && !self.locked {
+            self.lock_screen();
+        }
+    }
+
+    // Run the suspend action for the current power source
+    fn suspend(&self) {
+        let action = if self.on_battery {
+            &self.conf.suspend_on_battery_action
+        } else {
+            &self.conf.suspend_on_ac_action
+        };
+        let command = match action {
+            SuspendAction::Suspend => self
                 .system_actions
                 .get(&shortcuts::action::System::Suspend)
-                .map_or("systemctl suspend", |s| s.as_str());
-            crate::run_command(command.to_string());
+                .map_or("systemctl suspend", |s| s.as_str()),
+            SuspendAction::Hibernate => "systemctl hibernate",
+            SuspendAction::HybridSleep => "systemctl hybrid-sleep",
+            SuspendAction::SuspendThenHibernate => "systemctl suspend-then-hibernate",
+            SuspendAction::PowerOff => "systemctl poweroff",
+            SuspendAction::Command(command) => command,
+            SuspendAction::None => return,
+        };
+        crate::run_command(command.to_string());
+    }
+
+    fn update_suspend_idle(&mut self, is_idle: bool) {
+        if is_idle {
+            self.suspend();
         }
     }
 
-    // If screen off or suspend idle times have changed, recreate idle notifications.
+    fn current_stage(&self) -> control::Stage {
+        if self.locked {
+            control::Stage::Locked
+        } else if self.screen_off_since.is_some() {
+            // Fade surfaces are destroyed once the screen is off
+            if self.outputs.iter().any(|x| x.fade_surface.is_some()) {
+                control::Stage::Fading
+            } else {
+                control::Stage::ScreenOff
+            }
+        } else if self.is_dim_idle() {
+            control::Stage::Dimmed
+        } else {
+            control::Stage::Active
+        }
+    }
+
+    // Update the stage, and emit `StageChanged` if it has changed
+    fn update_stage(&mut self) {
+        let stage = self.current_stage();
+        if stage == self.stage {
+            return;
+        }
+        let screensaver_changed = stage.screensaver_active() != self.stage.screensaver_active();
+        self.stage = stage;
+        self.control_status.lock().unwrap().stage = stage;
+        if let Some(conn) = self.session_bus.clone() {
+            self.spawn(async move {
+                if let Err(err) = control::emit_stage_changed(&conn, stage).await {
+                    log::error!("failed to emit `StageChanged`: {}", err);
+                }
+                if screensaver_changed
+                    && let Err(err) =
+                        portal_inhibit::emit_state_changed(&conn, stage.screensaver_active()).await
+                {
+                    log::error!("failed to emit portal `StateChanged`: {}", err);
+                }
+            });
+        }
+    }
+
+    fn pause_for(&mut self, duration: Duration) {
+        if let Some(token) = self.pause_timer.take() {
+            self.loop_handle.remove(token);
+        }
+        if !duration.is_zero() {
+            let timer = timer::Timer::from_duration(duration);
+            let token = self
+                .loop_handle
+                .insert_source(timer, |_, _, state| {
+                    state.pause_timer = None;
+                    state.recreate_notifications();
+                    timer::TimeoutAction::Drop
+                })
+                .unwrap();
+            self.pause_timer = Some(token);
+        }
+        self.recreate_notifications();
+    }
+
+    // Inhibit idle stages while a toplevel is fullscreen, if its app ID is allowed
+    fn update_fullscreen_toplevel(&mut self) {
+        let app_ids = &self.conf.fullscreen_inhibit_app_ids;
+        let fullscreen_toplevel = self
+            .toplevels
+            .iter()
+            .any(|x| x.fullscreen && (app_ids.is_empty() || app_ids.contains(&x.app_id)));
+        if fullscreen_toplevel != self.fullscreen_toplevel {
+            self.fullscreen_toplevel = fullscreen_toplevel;
+            self.recreate_notifications();
+        }
+    }
+
+    fn idle_time(&self) -> Duration {
+        self.idle_since.map_or(Duration::ZERO, |x| x.elapsed())
+    }
+
+    // If dim, screen off, lock, or suspend idle times have changed, recreate idle notifications.
+    //
+    // Time the user has already been idle counts toward the new idle times.
     fn recreate_notifications(&mut self) {
-        let screen_off_time = if self.screensaver_inhibit {
+        let idle_time = self.idle_time();
+        // Pausing inhibits everything
+        let inhibit = if self.pause_timer.is_some() {
+            InhibitFlags::ALL
+        } else {
+            self.inhibit
+        };
+        let fullscreen_inhibit = if self.fullscreen_toplevel {
+            self.conf.fullscreen_inhibit
+        } else {
+            FullscreenInhibit::default()
+        };
+
+        let dim_time = if inhibit.idle || fullscreen_inhibit.dim {
             None
         } else {
-            self.conf.screen_off_time
+            self.conf.dim_time
         };
 
-        if self.screen_off_idle_notification.as_ref().map(|x| x.time) != screen_off_time {
-            self.screen_off_idle_notification =
-                screen_off_time.map(|time| IdleNotification::new(&self.inner, time));
+        if IdleNotification::update(
+            &mut self.dim_idle_notification,
+            &self.inner,
+            dim_time,
+            idle_time,
+        ) {
+            // Initially not idle; server sends `resumed` only after `idled`
+            self.update_dim_idle(false);
+        }
+
+        let screen_off_time = if inhibit.idle || fullscreen_inhibit.screen_off {
+            None
+        } else if self.on_battery {
+            self.conf.screen_off_on_battery_time
+        } else {
+            self.conf.screen_off_on_ac_time
+        };
+
+        if IdleNotification::update(
+            &mut self.screen_off_idle_notification,
+            &self.inner,
+            screen_off_time,
+            idle_time,
+        ) {
             // Initially not idle; server sends `resumed` only after `idled`
             self.update_screen_off_idle(false);
         }
 
-        let suspend_time = if self.screensaver_inhibit {
+        let lock_time = if inhibit.idle || fullscreen_inhibit.lock {
+            None
+        } else {
+            self.conf.lock_time
+        };
+
+        if IdleNotification::update(
+            &mut self.lock_idle_notification,
+            &self.inner,
+            lock_time,
+            idle_time,
+        ) {
+            // Initially not idle; server sends `resumed` only after `idled`
+            self.update_lock_idle(false);
+        }
+
+        // Suspending on idle is also an idle stage, so idle inhibitors block it
+        let suspend_time = if inhibit.idle || inhibit.suspend || fullscreen_inhibit.suspend {
             None
         } else if self.on_battery {
             self.conf.suspend_on_battery_time
@@ -210,24 +1002,201 @@ impl State {
             self.conf.suspend_on_ac_time
         };
 
-        if self.suspend_idle_notification.as_ref().map(|x| x.time) != suspend_time {
-            self.suspend_idle_notification =
-                suspend_time.map(|time| IdleNotification::new(&self.inner, time));
+        if IdleNotification::update(
+            &mut self.suspend_idle_notification,
+            &self.inner,
+            suspend_time,
+            idle_time,
+        ) {
             // Initially not idle; server sends `resumed` only after `idled`
             self.update_suspend_idle(false);
         }
+
+        self.control_status.lock().unwrap().timeouts = control::Timeouts {
+            dim: dim_time,
+            screen_off: screen_off_time,
+            lock: lock_time,
+            suspend: suspend_time,
+        };
+    }
+
+    // Run the critical battery action when the battery first becomes critical
+    fn update_critical_battery(&mut self) {
+        // In ms, or 0 if unknown
+        let time_to_empty = u64::try_from(self.battery_time_to_empty).unwrap_or(0) * 1000;
+        let below_percentage = self
+            .conf
+            .critical_battery_percentage
+            .is_some_and(|x| self.battery_percentage <= x);
+        let below_time = self
+            .conf
+            .critical_battery_time
+            .is_some_and(|x| time_to_empty != 0 && time_to_empty <= x.into());
+        let is_critical = self.on_battery && (below_percentage || below_time);
+        if is_critical && !self.battery_critical {
+            log::warn!(
+                "battery critical at {}%, running action {:?}",
+                self.battery_percentage,
+                self.conf.critical_battery_action
+            );
+            match self.conf.critical_battery_action {
+                CriticalBatteryAction::Warn => {
+                    let body = format!("{:.0}% remaining", self.battery_percentage);
+                    self.spawn(async move {
+                        if let Err(err) = send_notification("Battery critically low", &body).await {
+                            log::error!("failed to send critical battery notification: {}", err);
+                        }
+                    });
+                }
+                CriticalBatteryAction::Hibernate => {
+                    crate::run_command("systemctl hibernate".to_string())
+                }
+                CriticalBatteryAction::PowerOff => {
+                    crate::run_command("systemctl poweroff".to_string())
+                }
+                CriticalBatteryAction::None => {}
+            }
+        }
+        self.battery_critical = is_critical;
+    }
+
+    fn inhibitor_max_time(&self, application_name: &str) -> Option<Duration> {
+        self.conf
+            .inhibitor_max_time_for(application_name)
+            .map(|x| Duration::from_millis(x.into()))
+    }
+
+    // Schedule dropping the next inhibitor to reach its maximum time
+    fn update_inhibitor_expiry(&mut self) {
+        if let Some(token) = self.inhibitor_expiry_timer.take() {
+            self.loop_handle.remove(token);
+        }
+        let Some(expiry) = self
+            .inhibitors
+            .as_ref()
+            .and_then(|x| x.next_expiry(|name| self.inhibitor_max_time(name)))
+        else {
+            return;
+        };
+        let timer = timer::Timer::from_deadline(expiry);
+        let token = self
+            .loop_handle
+            .insert_source(timer, |_, _, state| {
+                state.inhibitor_expiry_timer = None;
+                state.expire_inhibitors();
+                timer::TimeoutAction::Drop
+            })
+            .unwrap();
+        self.inhibitor_expiry_timer = Some(token);
+    }
+
+    fn update_inhibitor_settings(&self) {
+        if let Some(inhibitors) = self.inhibitors.clone() {
+            let settings = inhibitor::Settings::new(&self.conf);
+            self.spawn(async move {
+                inhibitors.set_settings(settings).await;
+            });
+        }
+    }
+
+    // Start watching media players once `media_playback_inhibit` is enabled.
+    // If it is disabled later, their inhibitors are ignored.
+    fn update_watching_players(&mut self) {
+        if !self.conf.media_playback_inhibit || self.watching_players {
+            return;
+        }
+        let (Some(conn), Some(inhibitors)) = (self.session_bus.clone(), self.inhibitors.clone())
+        else {
+            return;
+        };
+        self.watching_players = true;
+        let scheduler = self.scheduler.clone();
+        self.spawn(async move {
+            if let Err(err) = mpris::receive_players_task(conn, inhibitors, scheduler).await {
+                log::error!("Watching MPRIS players: {}", err);
+            }
+        });
+    }
+
+    fn expire_inhibitors(&mut self) {
+        let Some(inhibitors) = self.inhibitors.clone() else {
+            return;
+        };
+        let expired = inhibitors.expired(|name| self.inhibitor_max_time(name));
+        if expired.is_empty() {
+            // Renewed or changed since the timer was set
+            self.update_inhibitor_expiry();
+            return;
+        }
+        // Removing sends `InhibitorsChanged`, which schedules the next expiry
+        self.spawn(async move {
+            for cookie in expired {
+                inhibitors.expire(cookie).await;
+            }
+        });
     }
 
     fn handle_event(&mut self, event: Event) {
         match event {
             Event::OnBattery(value) => {
                 self.on_battery = value;
+                self.recreate_notifications();
+                self.update_critical_battery();
+            }
+            Event::BatteryLevel {
+                percentage,
+                time_to_empty,
+            } => {
+                self.battery_percentage = percentage;
+                self.battery_time_to_empty = time_to_empty;
+                self.update_critical_battery();
             }
-            Event::ScreensaverInhibit(value) => {
-                self.screensaver_inhibit = value;
+            Event::Inhibit(flags) => {
+                self.inhibit = flags;
                 self.recreate_notifications();
             }
+            Event::InhibitorsChanged => {
+                self.update_inhibitor_expiry();
+            }
+            Event::SetScreensaverActive(true) => {
+                self.force_screen_off();
+            }
+            Event::SetScreensaverActive(false) | Event::SimulateUserActivity => {
+                self.reset_idle();
+            }
+            Event::Lock => {
+                self.lock_screen();
+            }
+            Event::PauseFor(duration) => {
+                self.pause_for(duration);
+            }
+            Event::Suspend => {
+                self.suspend();
+            }
+            Event::PrepareForSleep(sleep_lock) => {
+                self.prepare_for_sleep(sleep_lock);
+            }
+            Event::ResumeFromSleep => {
+                self.release_sleep_lock();
+                // Turns outputs back on
+                self.reset_idle();
+                self.resume_media();
+            }
+            Event::LockRequested => {
+                // Also for locks from `loginctl lock-session`, not just `lock_screen`
+                self.pause_media();
+            }
+            Event::SessionLocked(locked) => {
+                self.locked = locked;
+                if locked {
+                    // Safe to sleep now
+                    self.release_sleep_lock();
+                } else {
+                    self.resume_media();
+                }
+            }
         }
+        self.update_stage();
     }
 }
 
@@ -246,11 +1215,6 @@ fn main() {
         .bind::<ext_idle_notifier_v1::ExtIdleNotifierV1, _, _>(&qh, 1..=1, ())
         .unwrap();
 
-    let seat = globals
-        .bind::<wl_seat::WlSeat, _, _>(&qh, 1..=1, ())
-        .unwrap();
-    seat.get_pointer(&qh, ());
-
     let compositor = globals
         .bind::<wl_compositor::WlCompositor, _, _>(&qh, 1..=1, ())
         .unwrap();
@@ -271,7 +1235,26 @@ fn main() {
         )
         .unwrap();
 
+    // Optional; used to hide the tablet cursor over fade surfaces
+    let tablet_manager = globals
+        .bind::<zwp_tablet_manager_v2::ZwpTabletManagerV2, _, _>(&qh, 1..=1, ())
+        .ok();
+
+    // Optional; used to inhibit idle stages while a toplevel is fullscreen
+    let toplevel_info = globals
+        .bind::<zcosmic_toplevel_info_v1::ZcosmicToplevelInfoV1, _, _>(&qh, 2..=3, ())
+        .ok();
+    if toplevel_info.is_some()
+        && let Err(err) = globals
+            .bind::<ext_foreign_toplevel_list_v1::ExtForeignToplevelListV1, _, _>(&qh, 1..=1, ())
+    {
+        log::error!("failed to bind `ext_foreign_toplevel_list_v1`: {}", err);
+    }
+
     let config = cosmic_config::Config::new("com.system76.CosmicIdle", 1).unwrap();
+    if let Err(err) = CosmicIdleConfig::migrate(&config) {
+        log::error!("Migrating config: {}", err);
+    }
     let conf = CosmicIdleConfig::get_entry(&config).unwrap_or_else(|(errs, conf)| {
         for err in errs {
             log::error!("Loading config: {}", err);
@@ -283,36 +1266,98 @@ fn main() {
     let system_actions = shortcuts::system_actions(&shortcuts_config);
 
     let mut event_loop: EventLoop<State> = EventLoop::try_new().unwrap();
+    let (executor, scheduler) = calloop::futures::executor().unwrap();
+
+    let session_bus = match futures_lite::future::block_on(zbus::Connection::session()) {
+        Ok(conn) => Some(conn),
+        Err(err) => {
+            log::error!("failed to connect to session bus: {}", err);
+            None
+        }
+    };
+    let system_bus = match futures_lite::future::block_on(zbus::Connection::system()) {
+        Ok(conn) => Some(conn),
+        Err(err) => {
+            log::error!("failed to connect to system bus: {}", err);
+            None
+        }
+    };
+    let (sender, receiver) = channel::channel();
+    let inhibitors = session_bus.as_ref().map(|conn| {
+        Inhibitors::new(
+            conn.clone(),
+            sender.clone(),
+            inhibitor::Settings::new(&conf),
+        )
+    });
+    let screensaver_status = Arc::new(Mutex::new(freedesktop_screensaver::Status::default()));
+    let control_status = Arc::new(Mutex::new(control::Status::default()));
+
+    let inner = StateInner {
+        connection: connection.clone(),
+        registry: globals.registry().clone(),
+        compositor,
+        output_power_manager,
+        layer_shell,
+        viewporter,
+        single_pixel_buffer_manager,
+        idle_notifier,
+        tablet_manager,
+        seats: Vec::new(),
+        qh,
+    };
+    let activity_idle_notification =
+        IdleNotification::new(&inner, ACTIVITY_TIMEOUT, Duration::ZERO);
 
     let mut state = State {
-        inner: StateInner {
-            registry: globals.registry().clone(),
-            compositor,
-            output_power_manager,
-            layer_shell,
-            viewporter,
-            single_pixel_buffer_manager,
-            idle_notifier,
-            seat,
-            qh,
-        },
+        inner,
+        dim_idle_notification: None,
         screen_off_idle_notification: None,
+        lock_idle_notification: None,
         suspend_idle_notification: None,
+        activity_idle_notification,
+        force_screen_off_notification: None,
+        idle_since: None,
+        screen_off_since: None,
+        screensaver_status: screensaver_status.clone(),
+        lock_timer: None,
+        locked: false,
+        sleep_lock: None,
+        sleep_lock_timer: None,
+        stage: control::Stage::Active,
+        control_status: control_status.clone(),
+        pause_timer: None,
         outputs: Vec::new(),
         conf,
         on_battery: false,
-        screensaver_inhibit: false,
+        battery_percentage: 100.,
+        battery_time_to_empty: 0,
+        battery_critical: false,
+        inhibit: Default::default(),
+        inhibitors: inhibitors.clone(),
+        inhibitor_expiry_timer: None,
+        toplevel_info,
+        toplevels: Vec::new(),
+        paused_players: Arc::new(Mutex::new(Vec::new())),
+        watching_players: false,
+        fullscreen_toplevel: false,
         system_actions,
         loop_handle: event_loop.handle(),
+        scheduler: scheduler.clone(),
+        session_bus: session_bus.clone(),
+        system_bus,
     };
     globals.contents().with_list(|list| {
         for global in list {
             if global.interface == wl_output::WlOutput::interface().name {
                 state.add_output_global(global.name, global.version);
+            } else if global.interface == wl_seat::WlSeat::interface().name {
+                state.add_seat_global(global.name, global.version);
             }
         }
     });
     state.recreate_notifications();
+    state.update_watching_players();
 
     WaylandSource::new(connection, event_queue)
         .insert(event_loop.handle())
@@ -322,14 +1367,24 @@ fn main() {
         event_loop
             .handle()
             .insert_source(source, |(config, keys), _, state| {
+                let primary_seat = state.conf.primary_seat.clone();
                 state.conf.update_keys(&config, &keys);
+                if let Err(err) = state.conf.update_deprecated_keys(&config, &keys) {
+                    log::error!("Updating deprecated config keys: {}", err);
+                }
+                if state.conf.primary_seat != primary_seat {
+                    state.update_seats_idle();
+                }
+                state.update_fullscreen_toplevel();
                 state.recreate_notifications();
+                state.redraw_fade_surfaces();
+                state.update_inhibitor_expiry();
+                state.update_inhibitor_settings();
+                state.update_watching_players();
             })
             .unwrap();
     }
 
-    let (executor, scheduler) = calloop::futures::executor().unwrap();
-    let (sender, receiver) = channel::channel();
     let sender_clone = sender.clone();
     scheduler
         .schedule(async move {
@@ -338,13 +1393,91 @@ fn main() {
             }
         })
         .unwrap();
+    let sender_clone = sender.clone();
+    scheduler
+        .schedule(async move {
+            if let Err(err) = receive_battery_level_task(sender_clone).await {
+                log::error!("Getting battery level from upower: {}", err);
+            }
+        })
+        .unwrap();
+    let sender_clone = sender.clone();
     scheduler
         .schedule(async move {
-            if let Err(err) = freedesktop_screensaver::serve(sender).await {
-                log::error!("failed to serve FreeDesktop screensaver interface: {}", err);
+            if let Err(err) = logind::receive_sleep_task(sender_clone).await {
+                log::error!("Watching for sleep from logind: {}", err);
             }
         })
         .unwrap();
+    let sender_clone = sender.clone();
+    scheduler
+        .schedule(async move {
+            if let Err(err) = logind::receive_lock_task(sender_clone).await {
+                log::error!("Watching for session lock from logind: {}", err);
+            }
+        })
+        .unwrap();
+    if let Some(conn) = session_bus
+        && let Some(inhibitors) = inhibitors
+    {
+        let inhibitors_clone = inhibitors.clone();
+        scheduler
+            .schedule(async move {
+                if let Err(err) = inhibitors_clone.watch_clients().await {
+                    log::error!("failed to watch DBus clients: {}", err);
+                }
+            })
+            .unwrap();
+        let inhibitors_clone = inhibitors.clone();
+        scheduler
+            .schedule(async move {
+                if let Err(err) = logind::receive_inhibitors_task(inhibitors_clone).await {
+                    log::error!("Getting inhibitor locks from logind: {}", err);
+                }
+            })
+            .unwrap();
+        let conn_clone = conn.clone();
+        let inhibitors_clone = inhibitors.clone();
+        let sender_clone = sender.clone();
+        let control_status_clone = control_status.clone();
+        scheduler
+            .schedule(async move {
+                if let Err(err) = control::serve(
+                    conn_clone,
+                    inhibitors_clone,
+                    sender_clone,
+                    control_status_clone,
+                )
+                .await
+                {
+                    log::error!("failed to serve control interface: {}", err);
+                }
+            })
+            .unwrap();
+        let conn_clone = conn.clone();
+        let inhibitors_clone = inhibitors.clone();
+        scheduler
+            .schedule(async move {
+                if let Err(err) = freedesktop_screensaver::serve(
+                    conn_clone,
+                    inhibitors_clone,
+                    sender,
+                    screensaver_status,
+                )
+                .await
+                {
+                    log::error!("failed to serve FreeDesktop screensaver interface: {}", err);
+                }
+            })
+            .unwrap();
+        scheduler
+            .schedule(async move {
+                if let Err(err) = portal_inhibit::serve(conn, inhibitors, control_status).await {
+                    log::error!("failed to serve portal inhibit backend: {}", err);
+                }
+            })
+            .unwrap();
+    }
     event_loop
         .handle()
         .insert_source(executor, |_, _, _| {})
@@ -378,10 +1511,13 @@ impl Dispatch<wl_registry::WlRegistry, GlobalListContents> for State {
             } => {
                 if interface == "wl_output" {
                     state.add_output_global(name, version);
+                } else if interface == "wl_seat" {
+                    state.add_seat_global(name, version);
                 }
             }
             wl_registry::Event::GlobalRemove { name } => {
                 state.outputs.retain(|output| output.global_name != name);
+                state.remove_seat_global(name);
             }
             _ => {}
         }
@@ -402,21 +1538,41 @@ impl Dispatch<ext_idle_notification_v1::ExtIdleNotificationV1, ()> for State {
             ext_idle_notification_v1::Event::Resumed => false,
             _ => unreachable!(),
         };
-        if state
-            .screen_off_idle_notification
-            .as_ref()
-            .map(|x| &x.notification)
-            == Some(notification)
+
+        let inner = &state.inner;
+        let primary_seat = state.conf.primary_seat.as_deref();
+        if let Some(is_idle) =
+            state
+                .activity_idle_notification
+                .seat_event(inner, primary_seat, notification, is_idle)
         {
+            state.set_activity_idle(is_idle);
+        } else if let Some(x) = &mut state.force_screen_off_notification
+            && let Some(is_idle) = x.seat_event(inner, primary_seat, notification, is_idle)
+        {
+            state.set_force_screen_off_idle(is_idle);
+        } else if let Some(x) = &mut state.dim_idle_notification
+            && let Some(is_idle) = x.seat_event(inner, primary_seat, notification, is_idle)
+        {
+            x.set_idle(&state.inner, is_idle);
+            state.update_dim_idle(is_idle);
+        } else if let Some(x) = &mut state.screen_off_idle_notification
+            && let Some(is_idle) = x.seat_event(inner, primary_seat, notification, is_idle)
+        {
+            x.set_idle(&state.inner, is_idle);
             state.update_screen_off_idle(is_idle);
-        } else if state
-            .suspend_idle_notification
-            .as_ref()
-            .map(|x| &x.notification)
-            == Some(notification)
+        } else if let Some(x) = &mut state.lock_idle_notification
+            && let Some(is_idle) = x.seat_event(inner, primary_seat, notification, is_idle)
+        {
+            x.set_idle(&state.inner, is_idle);
+            state.update_lock_idle(is_idle);
+        } else if let Some(x) = &mut state.suspend_idle_notification
+            && let Some(is_idle) = x.seat_event(inner, primary_seat, notification, is_idle)
         {
+            x.set_idle(&state.inner, is_idle);
             state.update_suspend_idle(is_idle);
         }
+        state.update_stage();
     }
 }
 
@@ -424,7 +1580,6 @@ delegate_noop!(State: ignore wl_output::WlOutput);
 delegate_noop!(State: zwlr_output_power_manager_v1::ZwlrOutputPowerManagerV1);
 delegate_noop!(State: ignore zwlr_output_power_v1::ZwlrOutputPowerV1);
 delegate_noop!(State: ext_idle_notifier_v1::ExtIdleNotifierV1);
-delegate_noop!(State: ignore wl_seat::WlSeat); // TODO: Capabilties
 delegate_noop!(State: zwlr_layer_shell_v1::ZwlrLayerShellV1);
 delegate_noop!(State: wp_viewporter::WpViewporter);
 delegate_noop!(State: wp_viewport::WpViewport);
@@ -0,0 +1,167 @@
+// https://specifications.freedesktop.org/mpris-spec/latest/
+//
+// Media players that are playing are added as inhibitors, since many don't
+// call `Inhibit` themselves. Only watched with `media_playback_inhibit`.
+
+use futures_lite::StreamExt;
+
+use crate::inhibitor::Inhibitors;
+
+const NAME_PREFIX: &str = "org.mpris.MediaPlayer2.";
+
+#[zbus::proxy(
+    interface = "org.mpris.MediaPlayer2",
+    default_path = "/org/mpris/MediaPlayer2"
+)]
+trait MediaPlayer2 {
+    #[zbus(property)]
+    fn identity(&self) -> zbus::Result<String>;
+}
+
+#[zbus::proxy(
+    interface = "org.mpris.MediaPlayer2.Player",
+    default_path = "/org/mpris/MediaPlayer2"
+)]
+trait Player {
+    fn pause(&self) -> zbus::Result<()>;
+
+    fn play(&self) -> zbus::Result<()>;
+
+    #[zbus(property)]
+    fn playback_status(&self) -> zbus::Result<String>;
+}
+
+// Add an inhibitor while the player is playing, until it exits
+async fn watch_player(
+    conn: zbus::Connection,
+    inhibitors: Inhibitors,
+    name: String,
+) -> zbus::Result<()> {
+    let media_player = MediaPlayer2Proxy::builder(&conn)
+        .destination(name.as_str())?
+        .build()
+        .await?;
+    let player = PlayerProxy::builder(&conn)
+        .destination(name.as_str())?
+        .build()
+        .await?;
+    let identity = media_player
+        .identity()
+        .await
+        .unwrap_or_else(|_| name.clone());
+
+    let mut owner_stream = player.inner().receive_owner_changed().await?;
+    let mut status_stream = player.receive_playback_status_changed().await;
+    let mut cookie = None;
+    loop {
+        // Ends when the player exits
+        let status_changed = async { status_stream.next().await.is_some() };
+        let owner_changed = async { matches!(owner_stream.next().await, Some(Some(_))) };
+        if !futures_lite::future::or(status_changed, owner_changed).await {
+            break;
+        }
+        let playing = player.playback_status().await.is_ok_and(|x| x == "Playing");
+        match cookie {
+            None if playing => {
+                cookie = Some(inhibitors.add_mpris(identity.clone(), name.clone()).await);
+            }
+            Some(x) if !playing => {
+                inhibitors.remove(x).await;
+                cookie = None;
+            }
+            _ => {}
+        }
+    }
+
+    if let Some(cookie) = cookie {
+        inhibitors.remove(cookie).await;
+    }
+    Ok(())
+}
+
+fn spawn_watch_player(
+    conn: &zbus::Connection,
+    inhibitors: &Inhibitors,
+    scheduler: &calloop::futures::Scheduler<()>,
+    name: String,
+) {
+    let conn = conn.clone();
+    let inhibitors = inhibitors.clone();
+    let res = scheduler.schedule(async move {
+        if let Err(err) = watch_player(conn, inhibitors, name.clone()).await {
+            log::error!("failed to watch MPRIS player '{}': {}", name, err);
+        }
+    });
+    if let Err(err) = res {
+        log::error!("failed to schedule future: {}", err);
+    }
+}
+
+// Watch each MPRIS player on the session bus, including ones that start later
+pub async fn receive_players_task(
+    conn: zbus::Connection,
+    inhibitors: Inhibitors,
+    scheduler: calloop::futures::Scheduler<()>,
+) -> zbus::Result<()> {
+    let dbus = zbus::fdo::DBusProxy::new(&conn).await?;
+    let mut name_owner_stream = dbus.receive_name_owner_changed().await?;
+    for name in dbus.list_names().await? {
+        if name.starts_with(NAME_PREFIX) {
+            spawn_watch_player(&conn, &inhibitors, &scheduler, name.to_string());
+        }
+    }
+    while let Some(event) = name_owner_stream.next().await {
+        let args = event.args()?;
+        if args.name.starts_with(NAME_PREFIX)
+            && args.old_owner.is_none()
+            && args.new_owner.is_some()
+        {
+            spawn_watch_player(&conn, &inhibitors, &scheduler, args.name.to_string());
+        }
+    }
+    Ok(())
+}
+
+async fn player(conn: &zbus::Connection, name: &str) -> zbus::Result<PlayerProxy<'static>> {
+    PlayerProxy::builder(conn)
+        .destination(name.to_string())?
+        .cache_properties(zbus::proxy::CacheProperties::No)
+        .build()
+        .await
+}
+
+// Pause the player if it is playing, returning whether it was
+async fn pause_player(conn: &zbus::Connection, name: &str) -> zbus::Result<bool> {
+    let player = player(conn, name).await?;
+    if player.playback_status().await? != "Playing" {
+        return Ok(false);
+    }
+    player.pause().await?;
+    Ok(true)
+}
+
+// Pause all players that are playing, returning their bus names
+pub async fn pause_players(conn: &zbus::Connection) -> zbus::Result<Vec<String>> {
+    let dbus = zbus::fdo::DBusProxy::new(conn).await?;
+    let mut paused = Vec::new();
+    for name in dbus.list_names().await? {
+        if !name.starts_with(NAME_PREFIX) {
+            continue;
+        }
+        match pause_player(conn, &name).await {
+            Ok(true) => paused.push(name.to_string()),
+            Ok(false) => {}
+            Err(err) => log::error!("failed to pause MPRIS player '{}': {}", name, err),
+        }
+    }
+    Ok(paused)
+}
+
+pub async fn play_players(conn: &zbus::Connection, names: Vec<String>) {
+    for name in names {
+        let res = async { player(conn, &name).await?.play().await }.await;
+        if let Err(err) = res {
+            log::error!("failed to resume MPRIS player '{}': {}", name, err);
+        }
+    }
+}
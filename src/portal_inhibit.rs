@@ -0,0 +1,205 @@
+// https://flatpak.github.io/xdg-desktop-portal/docs/doc-org.freedesktop.impl.portal.Inhibit.html
+//
+// Backend for the xdg-desktop-portal `Inhibit` portal, used by sandboxed
+// applications. Described by `data/cosmic-idle.portal`, and selected by an
+// `org.freedesktop.impl.portal.Inhibit=cosmic_idle` entry in the
+// `cosmic-portals.conf` of xdg-desktop-portal-cosmic. Not D-Bus activated, so
+// only available while cosmic-idle runs in the session.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+use zbus::zvariant::{ObjectPath, OwnedObjectPath, OwnedValue, Value};
+
+use crate::{
+    control,
+    inhibitor::{InhibitFlags, Inhibitors},
+};
+
+const NAME: &str = "org.freedesktop.impl.portal.desktop.cosmic_idle";
+const PATH: &str = "/org/freedesktop/portal/desktop";
+
+// Flags for `Inhibit`; logout and user switch aren't handled here
+const INHIBIT_SUSPEND: u32 = 4;
+const INHIBIT_IDLE: u32 = 8;
+
+// `session-state` values for `StateChanged`
+const SESSION_STATE_RUNNING: u32 = 1;
+
+struct Inhibit {
+    inhibitors: Inhibitors,
+    status: Arc<Mutex<control::Status>>,
+    // Session handles of monitors created with `CreateMonitor`
+    monitors: Arc<Mutex<Vec<OwnedObjectPath>>>,
+}
+
+#[zbus::interface(name = "org.freedesktop.impl.portal.Inhibit")]
+impl Inhibit {
+    #[allow(clippy::too_many_arguments)]
+    async fn inhibit(
+        &self,
+        handle: OwnedObjectPath,
+        app_id: String,
+        _window: String,
+        flags: u32,
+        options: HashMap<String, OwnedValue>,
+        #[zbus(header)] header: zbus::message::Header<'_>,
+        #[zbus(object_server)] server: &zbus::ObjectServer,
+    ) -> zbus::fdo::Result<()> {
+        let flags = InhibitFlags {
+            idle: flags & INHIBIT_IDLE != 0,
+            suspend: flags & INHIBIT_SUSPEND != 0,
+        };
+        let reason = options
+            .get("reason")
+            .and_then(|x| x.downcast_ref::<String>().ok())
+            .unwrap_or_default();
+        let cookie = self.inhibitors.add(app_id, reason, &header, flags).await;
+        // Inhibitor is removed when the request is closed
+        let request = Request {
+            inhibitors: self.inhibitors.clone(),
+            cookie,
+        };
+        server.at(handle, request).await?;
+        Ok(())
+    }
+
+    async fn create_monitor(
+        &self,
+        _handle: OwnedObjectPath,
+        session_handle: OwnedObjectPath,
+        _app_id: String,
+        _window: String,
+        #[zbus(connection)] conn: &zbus::Connection,
+        #[zbus(object_server)] server: &zbus::ObjectServer,
+    ) -> zbus::fdo::Result<u32> {
+        let session = Session {
+            monitors: self.monitors.clone(),
+        };
+        server.at(&session_handle, session).await?;
+        self.monitors.lock().unwrap().push(session_handle.clone());
+
+        // Send the initial state once the monitor has been created
+        let screensaver_active = self.status.lock().unwrap().stage.screensaver_active();
+        let conn_clone = conn.clone();
+        conn.executor()
+            .spawn(
+                async move {
+                    let res = emit_session_state_changed(
+                        &conn_clone,
+                        session_handle.as_ref(),
+                        screensaver_active,
+                    )
+                    .await;
+                    if let Err(err) = res {
+                        log::error!("failed to emit portal `StateChanged`: {}", err);
+                    }
+                },
+                "portal initial state",
+            )
+            .detach();
+        Ok(0)
+    }
+
+    // Nothing is done on logout, so there's no response to wait for
+    fn query_end_response(&self, _session_handle: OwnedObjectPath) {}
+
+    #[zbus(signal)]
+    async fn state_changed(
+        emitter: &zbus::object_server::SignalEmitter<'_>,
+        session_handle: ObjectPath<'_>,
+        state: HashMap<&str, Value<'_>>,
+    ) -> zbus::Result<()>;
+}
+
+struct Request {
+    inhibitors: Inhibitors,
+    cookie: u32,
+}
+
+#[zbus::interface(name = "org.freedesktop.impl.portal.Request")]
+impl Request {
+    async fn close(
+        &self,
+        #[zbus(header)] header: zbus::message::Header<'_>,
+        #[zbus(object_server)] server: &zbus::ObjectServer,
+    ) -> zbus::fdo::Result<()> {
+        self.inhibitors.remove(self.cookie).await;
+        if let Some(path) = header.path() {
+            server.remove::<Self, _>(path).await?;
+        }
+        Ok(())
+    }
+}
+
+struct Session {
+    monitors: Arc<Mutex<Vec<OwnedObjectPath>>>,
+}
+
+#[zbus::interface(name = "org.freedesktop.impl.portal.Session")]
+impl Session {
+    async fn close(
+        &self,
+        #[zbus(header)] header: zbus::message::Header<'_>,
+        #[zbus(object_server)] server: &zbus::ObjectServer,
+    ) -> zbus::fdo::Result<()> {
+        if let Some(path) = header.path() {
+            self.monitors.lock().unwrap().retain(|x| **x != *path);
+            server.remove::<Self, _>(path).await?;
+        }
+        Ok(())
+    }
+
+    #[zbus(property, name = "version")]
+    fn version(&self) -> u32 {
+        1
+    }
+}
+
+async fn emit_session_state_changed(
+    conn: &zbus::Connection,
+    session_handle: ObjectPath<'_>,
+    screensaver_active: bool,
+) -> zbus::Result<()> {
+    let emitter = zbus::object_server::SignalEmitter::new(conn, PATH)?;
+    let state = HashMap::from([
+        ("screensaver-active", Value::from(screensaver_active)),
+        ("session-state", Value::from(SESSION_STATE_RUNNING)),
+    ]);
+    Inhibit::state_changed(&emitter, session_handle, state).await
+}
+
+// Notify monitors that the screensaver became active or inactive. It is
+// active while the screen is off or locked.
+pub async fn emit_state_changed(
+    conn: &zbus::Connection,
+    screensaver_active: bool,
+) -> zbus::Result<()> {
+    let monitors = match conn.object_server().interface::<_, Inhibit>(PATH).await {
+        Ok(iface) => iface.get().await.monitors.lock().unwrap().clone(),
+        // Not serving the portal backend
+        Err(zbus::Error::InterfaceNotFound) => return Ok(()),
+        Err(err) => return Err(err),
+    };
+    for session_handle in monitors {
+        emit_session_state_changed(conn, session_handle.as_ref(), screensaver_active).await?;
+    }
+    Ok(())
+}
+
+pub async fn serve(
+    conn: zbus::Connection,
+    inhibitors: Inhibitors,
+    status: Arc<Mutex<control::Status>>,
+) -> zbus::Result<()> {
+    let inhibit = Inhibit {
+        inhibitors,
+        status,
+        monitors: Arc::new(Mutex::new(Vec::new())),
+    };
+    conn.object_server().at(PATH, inhibit).await?;
+    conn.request_name_with_flags(NAME, zbus::fdo::RequestNameFlags::ReplaceExisting.into())
+        .await?;
+    Ok(())
+}
@@ -0,0 +1,168 @@
+use wayland_client::{
+    Connection, Dispatch, Proxy, QueueHandle, WEnum, delegate_noop, event_created_child,
+    protocol::{wl_pointer, wl_seat},
+};
+use wayland_protocols::wp::tablet::zv2::client::{
+    zwp_tablet_manager_v2, zwp_tablet_pad_group_v2, zwp_tablet_pad_ring_v2,
+    zwp_tablet_pad_strip_v2, zwp_tablet_pad_v2, zwp_tablet_seat_v2, zwp_tablet_tool_v2,
+    zwp_tablet_v2,
+};
+
+use crate::State;
+
+pub struct Seat {
+    pub seat: wl_seat::WlSeat,
+    pub global_name: u32,
+    // From `wl_seat.name`
+    pub name: Option<String>,
+    // Created while the seat has the pointer capability
+    pointer: Option<wl_pointer::WlPointer>,
+    tablet_seat: Option<zwp_tablet_seat_v2::ZwpTabletSeatV2>,
+}
+
+impl Seat {
+    pub fn new(
+        seat: wl_seat::WlSeat,
+        global_name: u32,
+        tablet_manager: Option<&zwp_tablet_manager_v2::ZwpTabletManagerV2>,
+        qh: &QueueHandle<State>,
+    ) -> Self {
+        let tablet_seat = tablet_manager.map(|x| x.get_tablet_seat(&seat, qh, ()));
+        Self {
+            seat,
+            global_name,
+            name: None,
+            pointer: None,
+            tablet_seat,
+        }
+    }
+
+    fn set_has_pointer(&mut self, has_pointer: bool, qh: &QueueHandle<State>) {
+        if has_pointer && self.pointer.is_none() {
+            self.pointer = Some(self.seat.get_pointer(qh, ()));
+        } else if !has_pointer && let Some(pointer) = self.pointer.take() {
+            release_pointer(&pointer);
+        }
+    }
+}
+
+fn release_pointer(pointer: &wl_pointer::WlPointer) {
+    if pointer.version() >= 3 {
+        pointer.release();
+    }
+}
+
+impl Drop for Seat {
+    fn drop(&mut self) {
+        if let Some(pointer) = &self.pointer {
+            release_pointer(pointer);
+        }
+        if let Some(tablet_seat) = &self.tablet_seat {
+            tablet_seat.destroy();
+        }
+        if self.seat.version() >= 5 {
+            self.seat.release();
+        }
+    }
+}
+
+impl Dispatch<wl_seat::WlSeat, ()> for State {
+    fn event(
+        state: &mut Self,
+        seat: &wl_seat::WlSeat,
+        event: wl_seat::Event,
+        _: &(),
+        _: &Connection,
+        qh: &QueueHandle<Self>,
+    ) {
+        let Some(x) = state.inner.seats.iter_mut().find(|x| &x.seat == seat) else {
+            return;
+        };
+        match event {
+            wl_seat::Event::Name { name } => {
+                x.name = Some(name);
+            }
+            wl_seat::Event::Capabilities {
+                capabilities: WEnum::Value(capabilities),
+            } => {
+                // Touch has no cursor, so only the pointer is needed
+                x.set_has_pointer(capabilities.contains(wl_seat::Capability::Pointer), qh);
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<zwp_tablet_seat_v2::ZwpTabletSeatV2, ()> for State {
+    fn event(
+        _: &mut Self,
+        _: &zwp_tablet_seat_v2::ZwpTabletSeatV2,
+        _: zwp_tablet_seat_v2::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+    }
+
+    event_created_child!(State, zwp_tablet_seat_v2::ZwpTabletSeatV2, [
+        zwp_tablet_seat_v2::EVT_TABLET_ADDED_OPCODE => (zwp_tablet_v2::ZwpTabletV2, ()),
+        zwp_tablet_seat_v2::EVT_TOOL_ADDED_OPCODE => (zwp_tablet_tool_v2::ZwpTabletToolV2, ()),
+        zwp_tablet_seat_v2::EVT_PAD_ADDED_OPCODE => (zwp_tablet_pad_v2::ZwpTabletPadV2, ())
+    ]);
+}
+
+impl Dispatch<zwp_tablet_v2::ZwpTabletV2, ()> for State {
+    fn event(
+        _: &mut Self,
+        tablet: &zwp_tablet_v2::ZwpTabletV2,
+        event: zwp_tablet_v2::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        if let zwp_tablet_v2::Event::Removed = event {
+            tablet.destroy();
+        }
+    }
+}
+
+// Pads have no cursor, but the compositor still sends them and their groups
+impl Dispatch<zwp_tablet_pad_v2::ZwpTabletPadV2, ()> for State {
+    fn event(
+        _: &mut Self,
+        pad: &zwp_tablet_pad_v2::ZwpTabletPadV2,
+        event: zwp_tablet_pad_v2::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        if let zwp_tablet_pad_v2::Event::Removed = event {
+            pad.destroy();
+        }
+    }
+
+    event_created_child!(State, zwp_tablet_pad_v2::ZwpTabletPadV2, [
+        zwp_tablet_pad_v2::EVT_GROUP_OPCODE => (zwp_tablet_pad_group_v2::ZwpTabletPadGroupV2, ())
+    ]);
+}
+
+impl Dispatch<zwp_tablet_pad_group_v2::ZwpTabletPadGroupV2, ()> for State {
+    fn event(
+        _: &mut Self,
+        _: &zwp_tablet_pad_group_v2::ZwpTabletPadGroupV2,
+        _: zwp_tablet_pad_group_v2::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+    }
+
+    event_created_child!(State, zwp_tablet_pad_group_v2::ZwpTabletPadGroupV2, [
+        zwp_tablet_pad_group_v2::EVT_RING_OPCODE => (zwp_tablet_pad_ring_v2::ZwpTabletPadRingV2, ()),
+        zwp_tablet_pad_group_v2::EVT_STRIP_OPCODE => (zwp_tablet_pad_strip_v2::ZwpTabletPadStripV2, ())
+    ]);
+}
+
+delegate_noop!(State: ignore zwp_tablet_pad_ring_v2::ZwpTabletPadRingV2);
+delegate_noop!(State: ignore zwp_tablet_pad_strip_v2::ZwpTabletPadStripV2);
+delegate_noop!(State: zwp_tablet_manager_v2::ZwpTabletManagerV2);
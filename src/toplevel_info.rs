@@ -0,0 +1,133 @@
+// Tracks fullscreen toplevels, which can inhibit idle stages.
+//
+// Toplevels are listed by `ext_foreign_toplevel_list_v1`, with their state
+// from `zcosmic_toplevel_info_v1`.
+
+use cosmic_protocols::toplevel_info::v1::client::{
+    zcosmic_toplevel_handle_v1, zcosmic_toplevel_info_v1,
+};
+use wayland_client::{Connection, Dispatch, QueueHandle, event_created_child};
+use wayland_protocols::ext::foreign_toplevel_list::v1::client::{
+    ext_foreign_toplevel_handle_v1, ext_foreign_toplevel_list_v1,
+};
+
+use crate::State;
+
+pub struct Toplevel {
+    handle: ext_foreign_toplevel_handle_v1::ExtForeignToplevelHandleV1,
+    cosmic_handle: zcosmic_toplevel_handle_v1::ZcosmicToplevelHandleV1,
+    pub app_id: String,
+    // Fullscreen, and not minimized
+    pub fullscreen: bool,
+}
+
+impl Drop for Toplevel {
+    fn drop(&mut self) {
+        self.cosmic_handle.destroy();
+        self.handle.destroy();
+    }
+}
+
+impl Dispatch<ext_foreign_toplevel_list_v1::ExtForeignToplevelListV1, ()> for State {
+    fn event(
+        state: &mut Self,
+        _: &ext_foreign_toplevel_list_v1::ExtForeignToplevelListV1,
+        event: ext_foreign_toplevel_list_v1::Event,
+        _: &(),
+        _: &Connection,
+        qh: &QueueHandle<Self>,
+    ) {
+        match event {
+            ext_foreign_toplevel_list_v1::Event::Toplevel { toplevel } => {
+                if let Some(toplevel_info) = &state.toplevel_info {
+                    let cosmic_handle = toplevel_info.get_cosmic_toplevel(&toplevel, qh, ());
+                    state.toplevels.push(Toplevel {
+                        handle: toplevel,
+                        cosmic_handle,
+                        app_id: String::new(),
+                        fullscreen: false,
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+
+    event_created_child!(State, ext_foreign_toplevel_list_v1::ExtForeignToplevelListV1, [
+        ext_foreign_toplevel_list_v1::EVT_TOPLEVEL_OPCODE => (ext_foreign_toplevel_handle_v1::ExtForeignToplevelHandleV1, ())
+    ]);
+}
+
+impl Dispatch<ext_foreign_toplevel_handle_v1::ExtForeignToplevelHandleV1, ()> for State {
+    fn event(
+        state: &mut Self,
+        handle: &ext_foreign_toplevel_handle_v1::ExtForeignToplevelHandleV1,
+        event: ext_foreign_toplevel_handle_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        match event {
+            ext_foreign_toplevel_handle_v1::Event::AppId { app_id } => {
+                if let Some(toplevel) = state.toplevels.iter_mut().find(|x| &x.handle == handle) {
+                    toplevel.app_id = app_id;
+                }
+                state.update_fullscreen_toplevel();
+            }
+            ext_foreign_toplevel_handle_v1::Event::Closed => {
+                state.toplevels.retain(|x| &x.handle != handle);
+                state.update_fullscreen_toplevel();
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<zcosmic_toplevel_handle_v1::ZcosmicToplevelHandleV1, ()> for State {
+    fn event(
+        state: &mut Self,
+        cosmic_handle: &zcosmic_toplevel_handle_v1::ZcosmicToplevelHandleV1,
+        event: zcosmic_toplevel_handle_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        match event {
+            zcosmic_toplevel_handle_v1::Event::State {
+                state: toplevel_state,
+            } => {
+                // Array of native endian `u32` values
+                let has_state = |x: zcosmic_toplevel_handle_v1::State| {
+                    toplevel_state
+                        .chunks_exact(4)
+                        .any(|chunk| u32::from_ne_bytes(chunk.try_into().unwrap()) == x as u32)
+                };
+                let fullscreen = has_state(zcosmic_toplevel_handle_v1::State::Fullscreen)
+                    && !has_state(zcosmic_toplevel_handle_v1::State::Minimized);
+                if let Some(toplevel) = state
+                    .toplevels
+                    .iter_mut()
+                    .find(|x| &x.cosmic_handle == cosmic_handle)
+                {
+                    toplevel.fullscreen = fullscreen;
+                }
+                state.update_fullscreen_toplevel();
+            }
+            _ => {}
+        }
+    }
+}
+
+// Toplevels are created through `ext_foreign_toplevel_list_v1` with version 2
+// and later, rather than the `toplevel` event
+impl Dispatch<zcosmic_toplevel_info_v1::ZcosmicToplevelInfoV1, ()> for State {
+    fn event(
+        _: &mut Self,
+        _: &zcosmic_toplevel_info_v1::ZcosmicToplevelInfoV1,
+        _: zcosmic_toplevel_info_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+    }
+}